@@ -0,0 +1,52 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::Terminal;
+
+/// Commands that copy to and paste from the system clipboard, with the environment variable
+/// of the display server they need.
+const TOOLS: [(&str, &[&str], &[&str]); 2] = [
+    ("WAYLAND_DISPLAY", &["wl-copy"], &["wl-paste", "--no-newline"]),
+    ("DISPLAY", &["xclip", "-selection", "clipboard"], &["xclip", "-selection", "clipboard", "-o"]),
+];
+
+/// Copies `text` to the system clipboard through the terminal, which works over SSH as well,
+/// and with `wl-copy` or `xclip` when one of them is available.
+pub fn copy(text: &str) {
+    Terminal::set_clipboard(text);
+    for (display, command, _) in TOOLS {
+        if env::var_os(display).is_none() {
+            continue;
+        }
+        let child = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            continue;
+        };
+        let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if child.wait().is_ok_and(|status| status.success()) && written {
+            return;
+        }
+    }
+}
+
+/// Reads the system clipboard with `wl-paste` or `xclip`. Terminals don't let programs read
+/// the clipboard, so without either there is nothing to read.
+pub fn paste() -> Option<String> {
+    TOOLS.iter().find_map(|(display, _, command)| {
+        env::var_os(display)?;
+        let output = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        String::from_utf8(output.stdout).ok()
+    })
+}
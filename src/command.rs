@@ -0,0 +1,303 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::paths;
+use crate::pattern;
+use crate::Config;
+
+/// Command names offered by tab-completion, in the order they are listed.
+const COMMANDS: [&str; 15] = [
+    "bdelete", "bnext", "bprev", "buffers", "edit", "explore", "find", "goto", "grep", "quit", "savemacro", "set", "substitute",
+    "wq", "write",
+];
+
+pub enum Command {
+    Write(Option<String>),
+    Quit { force: bool },
+    WriteQuit,
+    Edit { file_name: String, force: bool },
+    /// Opens the file finder.
+    Find,
+    /// Switches to the next open buffer, or to the previous one.
+    NextBuffer { reverse: bool },
+    /// Closes the current buffer.
+    CloseBuffer { force: bool },
+    ListBuffers,
+    /// Lists a directory, or the one of the current document, in the explorer.
+    Explore(Option<String>),
+    /// Searches the files of the project for a regular expression, followed by any `-g <glob>`
+    /// filters.
+    Grep(String),
+    /// Moves to a line and, optionally, a 1-based column.
+    Goto { line: Address, column: Option<usize> },
+    Set { option: String, value: String },
+    /// Stores the macro recorded in a register in the config file.
+    SaveMacro(char),
+    Substitute {
+        range: LineRange,
+        pattern: Regex,
+        replacement: String,
+        global: bool,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Base {
+    Line(usize),
+    /// A percentage of the document, like `50%`.
+    Percent(usize),
+    Current,
+    Last,
+}
+
+/// A line in an ex-style range: `12`, `50%`, `.`, `$`, optionally followed by `+N` or `-N`.
+#[derive(Clone, Copy)]
+pub struct Address {
+    base: Base,
+    offset: isize,
+}
+
+impl Address {
+    /// Resolves the address to a row index, given the cursor row and the number of rows.
+    pub fn resolve(&self, current: usize, len: usize) -> usize {
+        let row = match self.base {
+            Base::Line(line) => line.saturating_sub(1),
+            Base::Percent(percent) => (len.saturating_mul(percent) / 100).saturating_sub(1),
+            Base::Current => current,
+            Base::Last => len.saturating_sub(1),
+        };
+        row.saturating_add_signed(self.offset).min(len.saturating_sub(1))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl Command {
+    /// # Errors
+    ///
+    /// Will return a message describing why `input` is not a valid command.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (range, rest) = parse_range(input)?;
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (name, rest) = rest.split_at(name_len);
+        let (force, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let arg = rest.trim();
+
+        if name.is_empty() && range.is_some() {
+            return parse_goto(input);
+        }
+        if name.is_empty() && arg.is_empty() {
+            return Err(String::from("Empty command"));
+        }
+        if range.is_some() && name != "s" && name != "substitute" {
+            return Err(format!("{name} does not take a range"));
+        }
+        match name {
+            "w" | "write" => Ok(Self::Write(if arg.is_empty() {
+                None
+            } else {
+                Some(arg.to_string())
+            })),
+            "q" | "quit" => Ok(Self::Quit { force }),
+            "wq" | "x" => Ok(Self::WriteQuit),
+            "e" | "edit" if !arg.is_empty() => Ok(Self::Edit {
+                file_name: arg.to_string(),
+                force,
+            }),
+            "e" | "edit" => Err(String::from("Usage: edit <file>")),
+            "find" => Ok(Self::Find),
+            "bn" | "bnext" => Ok(Self::NextBuffer { reverse: false }),
+            "bp" | "bprev" => Ok(Self::NextBuffer { reverse: true }),
+            "bd" | "bdelete" => Ok(Self::CloseBuffer { force }),
+            "ls" | "buffers" => Ok(Self::ListBuffers),
+            "grep" if !arg.is_empty() => Ok(Self::Grep(arg.to_string())),
+            "grep" => Err(String::from("Usage: grep <regex> [-g <glob>]...")),
+            "explore" => Ok(Self::Explore(Some(arg.to_string()).filter(|arg| !arg.is_empty()))),
+            "goto" => parse_goto(arg),
+            "set" => match arg.split_once('=') {
+                Some((option, value)) => Ok(Self::Set {
+                    option: option.trim().to_string(),
+                    value: value.trim().to_string(),
+                }),
+                None => Err(String::from("Usage: set <option>=<value>")),
+            },
+            "savemacro" => match arg.parse::<char>() {
+                Ok(register) if register.is_ascii_alphanumeric() => Ok(Self::SaveMacro(register)),
+                _ => Err(String::from("Usage: savemacro <register>")),
+            },
+            "s" | "substitute" => parse_substitute(range, rest),
+            _ => Err(format!("Unknown command: {name}")),
+        }
+    }
+
+    /// Returns every possible completion of `input`, each being the complete new input.
+    pub fn complete(input: &str) -> Vec<String> {
+        let (_, rest) = parse_range(input).unwrap_or((None, input));
+        let prefix = &input[..input.len() - rest.len()];
+        match rest.split_once(' ') {
+            None => COMMANDS
+                .iter()
+                .filter(|name| name.starts_with(rest))
+                .map(|name| format!("{prefix}{name} "))
+                .collect(),
+            Some(("set", option)) if !option.contains('=') => {
+                let option = option.trim_start();
+                Config::OPTIONS
+                    .iter()
+                    .filter(|name| name.starts_with(option))
+                    .map(|name| format!("{prefix}set {name}="))
+                    .collect()
+            }
+            // Commands taking a file name complete it, relative to the working directory.
+            Some(("e" | "edit" | "e!" | "edit!" | "explore" | "w" | "write", arg)) => {
+                let arg = arg.trim_start();
+                let command = &rest[..rest.len() - arg.len()];
+                paths::complete(arg, Path::new(""))
+                    .into_iter()
+                    .map(|path| format!("{prefix}{command}{path}"))
+                    .collect()
+            }
+            Some(_) => Vec::new(),
+        }
+    }
+}
+
+fn parse_address(input: &str) -> Result<(Option<Address>, &str), String> {
+    let (base, rest) = if let Some(rest) = input.strip_prefix('.') {
+        (Some(Base::Current), rest)
+    } else if let Some(rest) = input.strip_prefix('$') {
+        (Some(Base::Last), rest)
+    } else {
+        let digits = input
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(input.len());
+        if digits > 0 {
+            let line = input[..digits]
+                .parse()
+                .map_err(|_| format!("Invalid line number: {}", &input[..digits]))?;
+            match input[digits..].strip_prefix('%') {
+                Some(rest) => (Some(Base::Percent(line)), rest),
+                None => (Some(Base::Line(line)), &input[digits..]),
+            }
+        } else {
+            (None, input)
+        }
+    };
+
+    let sign = match rest.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Ok((base.map(|base| Address { base, offset: 0 }), rest)),
+    };
+    let rest = &rest[1..];
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let amount: isize = if digits == 0 {
+        1
+    } else {
+        rest[..digits]
+            .parse()
+            .map_err(|_| format!("Invalid offset: {}", &rest[..digits]))?
+    };
+    let address = Address {
+        base: base.unwrap_or(Base::Current),
+        offset: sign * amount,
+    };
+    Ok((Some(address), &rest[digits..]))
+}
+
+fn parse_range(input: &str) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        let range = LineRange {
+            start: Address { base: Base::Line(1), offset: 0 },
+            end: Address { base: Base::Last, offset: 0 },
+        };
+        return Ok((Some(range), rest));
+    }
+    let (start, rest) = parse_address(input)?;
+    let Some(start) = start else {
+        return Ok((None, rest));
+    };
+    if let Some(rest) = rest.strip_prefix(',') {
+        match parse_address(rest)? {
+            (Some(end), rest) => Ok((Some(LineRange { start, end }), rest)),
+            (None, _) => Err(String::from("Missing end of range")),
+        }
+    } else {
+        Ok((Some(LineRange { start, end: start }), rest))
+    }
+}
+
+/// Parses the `line[:column]` target of `goto`, where `line` is an address or a range whose
+/// end is used.
+fn parse_goto(input: &str) -> Result<Command, String> {
+    let usage = || String::from("Usage: goto <line>[:<column>]");
+    let (range, rest) = parse_range(input.trim())?;
+    let line = range.ok_or_else(usage)?.end;
+    let column = match rest.strip_prefix(':') {
+        Some(column) => Some(column.parse().map_err(|_| format!("Invalid column: {column}"))?),
+        None if rest.is_empty() => None,
+        None => return Err(usage()),
+    };
+    Ok(Command::Goto { line, column })
+}
+
+/// Parses the `/pattern/replacement/flags` part of a substitution. Any punctuation character
+/// can be used instead of `/`, and a backslash escapes the delimiter.
+fn parse_substitute(range: Option<LineRange>, input: &str) -> Result<Command, String> {
+    let mut chars = input.chars();
+    let delimiter = match chars.next() {
+        Some(c) if c.is_ascii_punctuation() && c != '\\' => c,
+        _ => return Err(String::from("Usage: s/pattern/replacement/[g]")),
+    };
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        if c == delimiter && parts.len() < 3 {
+            parts.push(String::new());
+            continue;
+        }
+        let part = parts.last_mut().unwrap();
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push(c);
+                    part.push(next);
+                }
+                None => part.push(c),
+            }
+        } else {
+            part.push(c);
+        }
+    }
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    if pattern.is_empty() {
+        return Err(String::from("Empty search pattern"));
+    }
+    let pattern = pattern::compile(&pattern, false)?;
+    if let Some(flag) = flags.chars().find(|flag| *flag != 'g') {
+        return Err(format!("Unknown flag: {flag}"));
+    }
+    let current = Address { base: Base::Current, offset: 0 };
+    Ok(Command::Substitute {
+        range: range.unwrap_or(LineRange { start: current, end: current }),
+        pattern,
+        replacement,
+        global: flags.contains('g'),
+    })
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::keys;
+use crate::Key;
+
+const DEFAULT_STATUS_FORMAT: &str = "%M%R%f%m - %L lines%=%t | %l:%c | %e | %n";
+
+/// User settings, read from `$XDG_CONFIG_HOME/hecto/config` (or `~/.config/hecto/config`).
+///
+/// The file consists of `key = value` lines, `#` starts a comment and values may be quoted.
+pub struct Config {
+    /// Indents new lines like the previous one and dedents closing brackets.
+    pub auto_indent: bool,
+    /// Closes brackets and quotes as they are typed.
+    pub auto_pairs: bool,
+    /// Indents with spaces instead of tabs, unless the file is already indented with tabs.
+    pub expand_tab: bool,
+    /// Enables vim-style modal editing with normal, insert and visual modes.
+    pub modal: bool,
+    /// Number of columns between tab stops, and of spaces per indentation level unless the file
+    /// type or the file itself uses another width.
+    pub tab_width: usize,
+    /// Layout of the status bar. Supports `%f` file name, `%m` modified flag, `%t` file type,
+    /// `%l` line, `%c` display column, `%L` line count, `%e` encoding, `%n` line ending, `%M`
+    /// mode (empty without `modal`), `%R` the register a macro is being recorded into and `%%`.
+    /// Everything after `%=` is aligned to the right.
+    pub status_format: String,
+    /// Keyboard macros by register, stored as `macro.<register> = <keys>`.
+    pub macros: HashMap<char, Vec<Key>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auto_indent: true,
+            auto_pairs: true,
+            expand_tab: true,
+            modal: false,
+            tab_width: 4,
+            status_format: String::from(DEFAULT_STATUS_FORMAT),
+            macros: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Every option accepted by `set`, both in the config file and on the command line.
+    pub const OPTIONS: [&'static str; 6] = ["autoindent", "autopairs", "expandtab", "modal", "statusformat", "tabwidth"];
+
+    /// Loads the config file, falling back to the defaults for every missing or invalid entry.
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Some(contents) = Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            for line in contents.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((key, value)) = line.split_once('=') {
+                    let _ = config.set(key.trim(), unquote(value.trim()));
+                }
+            }
+        }
+        config
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("hecto").join("config"))
+    }
+
+    /// # Errors
+    ///
+    /// Will return a message if the key is unknown or the value is invalid for it.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "autoindent" => self.auto_indent = parse_bool(value)?,
+            "autopairs" => self.auto_pairs = parse_bool(value)?,
+            "expandtab" => self.expand_tab = parse_bool(value)?,
+            "modal" => self.modal = parse_bool(value)?,
+            // `status_format` is the name the option was first introduced with.
+            "statusformat" | "status_format" => self.status_format = value.to_string(),
+            "tabwidth" => {
+                self.tab_width = match value.parse() {
+                    Ok(width) if width > 0 => width,
+                    _ => return Err(format!("Invalid tab width: {value}")),
+                }
+            }
+            _ => {
+                let register = key
+                    .strip_prefix("macro.")
+                    .and_then(|register| register.parse::<char>().ok())
+                    .filter(char::is_ascii_alphanumeric)
+                    .ok_or_else(|| format!("Unknown option: {key}"))?;
+                self.macros.insert(register, keys::parse_keys(value)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `key = value` to the config file, replacing an existing entry for `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an `std::io::Error` if the config file could not be written.
+    pub fn persist(key: &str, value: &str) -> Result<(), io::Error> {
+        let path = Self::path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+        let entry = format!("{key} = \"{value}\"");
+        let mut replaced = false;
+        let mut lines: Vec<String> = contents
+            .lines()
+            .map(|line| match line.split_once('=') {
+                Some((existing, _)) if existing.trim() == key && !line.trim_start().starts_with('#') => {
+                    replaced = true;
+                    entry.clone()
+                }
+                _ => line.to_string(),
+            })
+            .collect();
+        if !replaced {
+            lines.push(entry);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, lines.join("\n") + "\n")
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => Err(format!("Expected true or false, got: {value}")),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
@@ -1,17 +1,123 @@
+use std::cmp;
+use std::collections::HashMap;
 use std::fs;
 use std::io::{Error, Write};
 
 use crate::FileType;
+use crate::History;
+use regex::Regex;
 use crate::Row;
 use crate::Position;
 use crate::SearchDirection;
 
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn detect(contents: &str) -> Self {
+        match contents.find('\n') {
+            Some(idx) if contents[..idx].ends_with('\r') => Self::CrLf,
+            _ => Self::Lf,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+        }
+    }
+}
+
+/// How a document is indented.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Indent {
+    /// Guesses the indentation of `contents`: tabs if more rows start with a tab than with a
+    /// space, otherwise the most common step between the indentation of consecutive rows.
+    fn detect(contents: &str) -> Option<Self> {
+        let (mut tabs, mut spaces) = (0, 0);
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut previous = 0;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            if line.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            if indent > 0 {
+                spaces += 1;
+            }
+            // Steps of a single space are mostly alignment, like in block comments.
+            if (2..=8).contains(&indent.saturating_sub(previous)) {
+                *steps.entry(indent - previous).or_default() += 1;
+            }
+            previous = indent;
+        }
+        if tabs > spaces {
+            return Some(Self::Tabs);
+        }
+        steps
+            .into_iter()
+            .max_by_key(|(width, count)| (*count, cmp::Reverse(*width)))
+            .map(|(width, _)| Self::Spaces(width))
+    }
+
+    pub fn unit(self) -> String {
+        match self {
+            Self::Tabs => String::from("\t"),
+            Self::Spaces(width) => " ".repeat(width),
+        }
+    }
+}
+
+/// How many rows are searched for a matching bracket before giving up.
+const MAX_BRACKET_SCAN_ROWS: usize = 10_000;
+
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl From<&str> for CharClass {
+    fn from(grapheme: &str) -> Self {
+        match grapheme.chars().next() {
+            Some(c) if c.is_whitespace() => Self::Whitespace,
+            Some(c) if c.is_alphanumeric() || c == '_' => Self::Word,
+            _ => Self::Punctuation,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Document {
     rows: Vec<Row>,
     pub file_name: Option<String>,
     changed: bool,
     file_type: FileType,
+    line_ending: LineEnding,
+    /// The indentation detected when the document was opened.
+    indent: Option<Indent>,
+    history: History,
 }
 
 impl Document {
@@ -21,7 +127,7 @@ impl Document {
         let mut rows = Vec::new();
         for value in contents.lines() {
             let mut row = Row::from(value);
-            row.highlight(&file_type.highlighting_options(), None);
+            row.highlight(file_type.highlighting_options());
             rows.push(row);
         }
         Ok(
@@ -30,12 +136,47 @@ impl Document {
                 file_name: Some(filename.to_string()),
                 changed: false,
                 file_type,
+                line_ending: LineEnding::detect(&contents),
+                indent: Indent::detect(&contents),
+                history: History::default(),
             }
         )
     }
 
-    pub fn file_type(&self) -> String {
-        self.file_type.name()
+    /// Creates an unchanged document named `name` that holds `text`, like a listing generated by
+    /// the editor.
+    pub fn from_text(name: &str, text: &str) -> Self {
+        Self {
+            rows: text.lines().map(Row::from).collect(),
+            file_name: Some(name.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Creates an empty document that will be saved as `filename`.
+    pub fn new(filename: &str) -> Self {
+        Self {
+            file_name: Some(filename.to_string()),
+            file_type: FileType::from(filename),
+            ..Self::default()
+        }
+    }
+
+    pub fn file_type(&self) -> &FileType {
+        &self.file_type
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn indent(&self) -> Option<Indent> {
+        self.indent
+    }
+
+    /// Documents are always read with `fs::read_to_string`, so anything that opened is UTF-8.
+    pub fn encoding(&self) -> &'static str {
+        "utf-8"
     }
 
     pub fn row(&self, idx: usize) -> Option<&Row> {
@@ -52,16 +193,68 @@ impl Document {
 
     fn insert_new_line(&mut self, pos: &Position) {
         if pos.y == self.len() {
+            self.record(pos.y, 0, 1);
             self.rows.push(Row::default());
             return;
         }
+        self.record(pos.y, 1, 2);
         let current_row = &mut self.rows[pos.y];
         let mut new_row = current_row.split(pos.x);
-        current_row.highlight(&self.file_type.highlighting_options(), None);
-        new_row.highlight(&self.file_type.highlighting_options(), None);
+        current_row.highlight(self.file_type.highlighting_options());
+        new_row.highlight(self.file_type.highlighting_options());
         self.rows.insert(pos.y + 1, new_row);
     }
 
+    /// Splits the row at `pos` and indents the new row like the current one, one `indent_unit`
+    /// deeper after an opening bracket. Returns the position after the indentation.
+    pub fn insert_indented_newline(&mut self, pos: &Position, indent_unit: &str) -> Position {
+        let Some(row) = self.rows.get(pos.y) else {
+            self.insert(pos, '\n');
+            return Position { x: 0, y: pos.y.saturating_add(1) };
+        };
+        let before = row.slice(0, pos.x);
+        let indent: String = before.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        let opener = before.trim_end().chars().last().filter(|c| self.file_type.opens_indent(*c));
+        let closes_opener = row
+            .grapheme(pos.x)
+            .and_then(|grapheme| grapheme.chars().next())
+            .is_some_and(|c| self.file_type.closes_indent(c));
+
+        self.insert_new_line(pos);
+        self.changed = true;
+        let next = Position { x: 0, y: pos.y + 1 };
+        if opener.is_none() {
+            return self.insert_str(&next, &indent);
+        }
+        let cursor = self.insert_str(&next, &format!("{indent}{indent_unit}"));
+        // Typing Enter between a pair of brackets moves the closing one onto a row of its own.
+        if closes_opener {
+            self.insert_new_line(&cursor);
+            self.insert_str(&Position { x: 0, y: cursor.y + 1 }, &indent);
+        }
+        cursor
+    }
+
+    /// Removes one level of indentation, but no more than `limit` graphemes, from the start of
+    /// row `y` and returns how many graphemes were removed.
+    pub fn outdent_row(&mut self, y: usize, indent_unit: &str, limit: usize) -> usize {
+        let Some(row) = self.rows.get(y) else {
+            return 0;
+        };
+        let removed = if row.as_str().starts_with('\t') {
+            1
+        } else {
+            row.as_str()
+                .chars()
+                .take(indent_unit.len())
+                .take_while(|c| *c == ' ')
+                .count()
+        }
+        .min(limit);
+        self.delete_range(&Position { x: 0, y }, &Position { x: removed, y });
+        removed
+    }
+
     pub fn insert(&mut self, pos: &Position, c: char) {
         if pos.y > self.len() {
             return;
@@ -72,14 +265,16 @@ impl Document {
             return;
         }
         if pos.y == self.len() {
+            self.record(pos.y, 0, 1);
             let mut row = Row::default();
             row.insert(0, c);
-            row.highlight(&self.file_type.highlighting_options(), None);
+            row.highlight(self.file_type.highlighting_options());
             self.rows.push(row);
         } else {
+            self.record(pos.y, 1, 1);
             let row = self.rows.get_mut(pos.y).unwrap();
             row.insert(pos.x, c);
-            row.highlight(&self.file_type.highlighting_options(), None);
+            row.highlight(self.file_type.highlighting_options());
         }
     }
 
@@ -90,14 +285,16 @@ impl Document {
         self.changed = true;
         // If backspace is pressed at the beginning of a line, append it to the preceding line
         if pos.x == self.rows.get_mut(pos.y).unwrap().len() && pos.y < self.len() - 1 {
+            self.record(pos.y, 2, 1);
             let next_row = self.rows.remove(pos.y + 1);
             let row = self.rows.get_mut(pos.y).unwrap();
             row.append(&next_row);
-            row.highlight(&self.file_type.highlighting_options(), None);
+            row.highlight(self.file_type.highlighting_options());
         } else {
+            self.record(pos.y, 1, 1);
             let row = self.rows.get_mut(pos.y).unwrap();
             row.delete(pos.x);
-            row.highlight(&self.file_type.highlighting_options(), None);
+            row.highlight(self.file_type.highlighting_options());
         }
     }
 
@@ -107,8 +304,8 @@ impl Document {
             self.file_type = FileType::from(file_name);
             for row in &mut self.rows {
                 file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-                row.highlight(&self.file_type.highlighting_options(), None)
+                file.write_all(self.line_ending.as_str().as_bytes())?;
+                row.highlight(self.file_type.highlighting_options())
             }
             self.changed = false;
         }
@@ -119,7 +316,7 @@ impl Document {
         self.changed
     }
 
-    pub fn find(&self, query: &str, pos: &Position, dir: SearchDirection) -> Option<Position> {
+    pub fn find(&self, query: &str, pos: &Position, dir: SearchDirection, ignore_case: bool) -> Option<Position> {
         if pos.y >= self.rows.len()  {
             return None;
         }
@@ -137,7 +334,7 @@ impl Document {
         };
         for _ in start..end {
             if let Some(row) = self.rows.get(pos.y) {
-                if let Some(x) = row.find(&query, pos.x, dir) {
+                if let Some(x) = row.find(query, pos.x, dir, ignore_case) {
                     pos.x = x;
                     return Some(pos);
                 }
@@ -155,9 +352,477 @@ impl Document {
         None
     }
 
-    pub fn highlight(&mut self, word: Option<&str>) {
+    /// Replaces matches of `pattern` in the rows `start..=end` and returns the number of
+    /// replacements.
+    pub fn substitute(&mut self, start: usize, end: usize, pattern: &Regex, replacement: &str, global: bool) -> usize {
+        let mut count = 0;
+        for y in start..cmp::min(end.saturating_add(1), self.len()) {
+            if !pattern.is_match(self.rows[y].as_str()) {
+                continue;
+            }
+            self.changed = true;
+            self.record(y, 1, 1);
+            let row = &mut self.rows[y];
+            count += row.replace(pattern, replacement, global);
+            row.highlight(self.file_type.highlighting_options());
+        }
+        count
+    }
+
+    /// Moves `pos` onto the document: at most the last row, and at most its end.
+    pub fn clamp(&self, pos: &Position) -> Position {
+        let y = cmp::min(pos.y, self.len().saturating_sub(1));
+        let x = cmp::min(pos.x, self.rows.get(y).map_or(0, Row::len));
+        Position { x, y }
+    }
+
+    /// Returns the text from `start` up to, but excluding, `end`.
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let (start, end) = (self.clamp(start), self.clamp(end));
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            let row = &self.rows[y];
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            text.push_str(row.slice(from, to));
+            if y < end.y {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Inserts `text`, which may span several lines, and returns the position right after it.
+    pub fn insert_str(&mut self, pos: &Position, text: &str) -> Position {
+        if pos.y > self.len() || text.is_empty() {
+            return *pos;
+        }
+        self.changed = true;
+        let old_len = if pos.y == self.len() { 0 } else { 1 };
+        self.record(pos.y, old_len, text.split('\n').count());
+        if pos.y == self.len() {
+            self.rows.push(Row::default());
+        }
+        let mut lines = text.split('\n');
+        let row = &mut self.rows[pos.y];
+        let tail = row.split(pos.x);
+        row.append(&Row::from(lines.next().unwrap_or_default()));
+        let mut y = pos.y;
+        for line in lines {
+            y += 1;
+            self.rows.insert(y, Row::from(line));
+        }
+        let end = Position { x: self.rows[y].len(), y };
+        self.rows[y].append(&tail);
+        for row in &mut self.rows[pos.y..=y] {
+            row.highlight(self.file_type.highlighting_options());
+        }
+        end
+    }
+
+    /// Deletes the text from `start` up to, but excluding, `end` and returns it.
+    pub fn delete_range(&mut self, start: &Position, end: &Position) -> String {
+        let (start, end) = (self.clamp(start), self.clamp(end));
+        if self.is_empty() || start >= end {
+            return String::new();
+        }
+        let text = self.text(&start, &end);
+        self.changed = true;
+        self.record(start.y, end.y - start.y + 1, 1);
+        let tail = self.rows[end.y].split(end.x);
+        self.rows[start.y].split(start.x);
+        self.rows.drain(start.y + 1..=end.y);
+        let row = &mut self.rows[start.y];
+        row.append(&tail);
+        row.highlight(self.file_type.highlighting_options());
+        text
+    }
+
+    /// Deletes the rows `start..=end` and returns them, each terminated by a newline.
+    pub fn delete_rows(&mut self, start: usize, end: usize) -> String {
+        let end = cmp::min(end, self.len().saturating_sub(1));
+        if start > end || start >= self.len() {
+            return String::new();
+        }
+        self.changed = true;
+        self.record(start, end - start + 1, 0);
+        self.rows
+            .drain(start..=end)
+            .map(|row| format!("{}\n", row.as_str()))
+            .collect()
+    }
+
+    /// Inserts every line of `text` as a new row, starting at row `at`.
+    pub fn insert_rows(&mut self, at: usize, text: &str) {
+        let at = cmp::min(at, self.len());
+        let lines = text.strip_suffix('\n').unwrap_or(text).split('\n');
+        self.record(at, 0, lines.clone().count());
+        for (idx, line) in lines.enumerate() {
+            let mut row = Row::from(line);
+            row.highlight(self.file_type.highlighting_options());
+            self.rows.insert(at + idx, row);
+        }
+        self.changed = true;
+    }
+
+    /// Adds `indent_unit` to the start of the non-blank rows `start..=end`.
+    pub fn indent_rows(&mut self, start: usize, end: usize, indent_unit: &str) {
+        for y in start..=cmp::min(end, self.len().saturating_sub(1)) {
+            if !self.rows[y].as_str().trim().is_empty() {
+                self.insert_str(&Position { x: 0, y }, indent_unit);
+            }
+        }
+    }
+
+    /// Removes one level of indentation from the rows `start..=end`.
+    pub fn outdent_rows(&mut self, start: usize, end: usize, indent_unit: &str) {
+        for y in start..=cmp::min(end, self.len().saturating_sub(1)) {
+            self.outdent_row(y, indent_unit, usize::MAX);
+        }
+    }
+
+    /// Inserts a copy of the rows `start..=end` right after them. Returns `false` if there are no
+    /// such rows.
+    pub fn duplicate_rows(&mut self, start: usize, end: usize) -> bool {
+        let text = self.rows_text(start, end);
+        if text.is_empty() {
+            return false;
+        }
+        self.insert_rows(end.saturating_add(1), &text);
+        true
+    }
+
+    /// Moves the rows `start..=end` one row up, or down without `up`. Returns `false` if they are
+    /// already at the edge of the document.
+    pub fn move_rows(&mut self, start: usize, end: usize, up: bool) -> bool {
+        if end >= self.len() || (up && start == 0) || (!up && end + 1 >= self.len()) {
+            return false;
+        }
+        if up {
+            let text = self.delete_rows(start - 1, start - 1);
+            self.insert_rows(end, &text);
+        } else {
+            let text = self.delete_rows(end + 1, end + 1);
+            self.insert_rows(start, &text);
+        }
+        true
+    }
+
+    /// Joins row `y` with the next one, replacing the whitespace between them with a single
+    /// space, or with nothing next to an empty row or before a closing bracket. Returns where
+    /// the rows were joined.
+    pub fn join_rows(&mut self, y: usize) -> Option<Position> {
+        let next = self.rows.get(y.checked_add(1)?)?;
+        let next_indent = indentation(next);
+        let closes_bracket = next
+            .as_str()
+            .trim_start()
+            .chars()
+            .next()
+            .and_then(|c| FileType::bracket_pair(c).filter(|(_, close)| *close == c))
+            .is_some();
+        let next_is_empty = next.as_str().trim().is_empty();
+        let x = self.rows[y].as_str().trim_end().graphemes(true).count();
+        self.delete_range(&Position { x, y }, &Position { x: next_indent, y: y + 1 });
+        if x > 0 && !next_is_empty && !closes_bracket {
+            self.insert_str(&Position { x, y }, " ");
+        }
+        Some(Position { x, y })
+    }
+
+    /// Returns the rows `start..=end`, each terminated by a newline.
+    pub fn rows_text(&self, start: usize, end: usize) -> String {
+        self.rows
+            .iter()
+            .take(end.saturating_add(1))
+            .skip(start)
+            .map(|row| format!("{}\n", row.as_str()))
+            .collect()
+    }
+
+    /// Comments out the rows `start..=end` with the file type's line comment token, aligned to
+    /// the least indented row, or uncomments them if every non-blank row is commented already.
+    /// Falls back to a block comment around the rows. Returns `false` if the file type has no
+    /// comment syntax.
+    pub fn toggle_comment(&mut self, start: usize, end: usize) -> bool {
+        let end = cmp::min(end, self.len().saturating_sub(1));
+        let rows: Vec<usize> = (start..=end)
+            .filter(|y| self.rows.get(*y).is_some_and(|row| !row.as_str().trim().is_empty()))
+            .collect();
+        let (Some(&first), Some(&last)) = (rows.first(), rows.last()) else {
+            return self.file_type.line_comment().is_some() || self.file_type.block_comment().is_some();
+        };
+        if let Some(token) = self.file_type.line_comment().map(str::to_string) {
+            let commented = rows
+                .iter()
+                .all(|y| self.rows[*y].as_str().trim_start().starts_with(&token));
+            let indent = rows.iter().map(|y| indentation(&self.rows[*y])).min().unwrap_or(0);
+            for y in rows {
+                if commented {
+                    let x = indentation(&self.rows[y]);
+                    self.remove_token(Position { x, y }, &token);
+                } else {
+                    self.insert_str(&Position { x: indent, y }, &format!("{token} "));
+                }
+            }
+            return true;
+        }
+        let Some((open, close)) = self.file_type.block_comment().map(|(open, close)| (open.to_string(), close.to_string())) else {
+            return false;
+        };
+        let commented = self.rows[first].as_str().trim_start().starts_with(&open)
+            && self.rows[last].as_str().trim_end().ends_with(&close);
+        if commented {
+            let trimmed_len = self.rows[last].as_str().trim_end().graphemes(true).count();
+            let x = trimmed_len - close.graphemes(true).count();
+            let x = if x > 0 && self.rows[last].grapheme(x - 1) == Some(" ") { x - 1 } else { x };
+            self.delete_range(&Position { x, y: last }, &Position { x: trimmed_len, y: last });
+            let x = indentation(&self.rows[first]);
+            self.remove_token(Position { x, y: first }, &open);
+        } else {
+            let x = self.rows[last].as_str().trim_end().graphemes(true).count();
+            self.insert_str(&Position { x, y: last }, &format!(" {close}"));
+            let x = indentation(&self.rows[first]);
+            self.insert_str(&Position { x, y: first }, &format!("{open} "));
+        }
+        true
+    }
+
+    /// Deletes `token` at `pos`, along with the space after it.
+    fn remove_token(&mut self, pos: Position, token: &str) {
+        let mut end = Position { x: pos.x + token.graphemes(true).count(), y: pos.y };
+        if self.rows[pos.y].grapheme(end.x) == Some(" ") {
+            end.x += 1;
+        }
+        self.delete_range(&pos, &end);
+    }
+
+    /// Finds the bracket matching the one at `pos`, skipping brackets in strings and comments.
+    pub fn matching_bracket(&self, pos: &Position) -> Option<Position> {
+        let (_, c) = self.rows.get(pos.y)?.brackets().into_iter().find(|(x, _)| *x == pos.x)?;
+        let (open, close) = FileType::bracket_pair(c)?;
+        let forward = c == open;
+        let rows: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new(pos.y..cmp::min(self.len(), pos.y.saturating_add(MAX_BRACKET_SCAN_ROWS)))
+        } else {
+            Box::new((pos.y.saturating_sub(MAX_BRACKET_SCAN_ROWS)..=pos.y).rev())
+        };
+        let mut depth = 0_usize;
+        for y in rows {
+            let mut brackets = self.rows[y].brackets();
+            if !forward {
+                brackets.reverse();
+            }
+            for (x, bracket) in brackets {
+                if y == pos.y && (if forward { x <= pos.x } else { x >= pos.x }) {
+                    continue;
+                }
+                if bracket == c {
+                    depth += 1;
+                } else if bracket == if forward { close } else { open } {
+                    if depth == 0 {
+                        return Some(Position { x, y });
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// Start of the next word, like vim's `w`, or of the next WORD of non-blank characters with
+    /// `big`, like `W`. Empty rows count as words.
+    pub fn next_word_start(&self, pos: &Position, big: bool) -> Position {
+        let class = self.class_at(pos, big);
+        let mut pos = *pos;
+        while let Some(next) = self.next_position(&pos) {
+            let crossed_row = next.y != pos.y;
+            pos = next;
+            if crossed_row && self.rows[pos.y].is_empty() {
+                return pos;
+            }
+            if crossed_row || self.class_at(&pos, big) != class {
+                break;
+            }
+        }
+        while self.class_at(&pos, big) == CharClass::Whitespace {
+            match self.next_position(&pos) {
+                Some(next) if next.y != pos.y && self.rows[next.y].is_empty() => return next,
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        pos
+    }
+
+    /// Start of the current or previous word, like vim's `b` (or `B` with `big`).
+    pub fn prev_word_start(&self, pos: &Position, big: bool) -> Position {
+        let mut pos = *pos;
+        loop {
+            match self.prev_position(&pos) {
+                Some(prev) => pos = prev,
+                None => return pos,
+            }
+            if pos.x == 0 && self.rows[pos.y].is_empty() {
+                return pos;
+            }
+            if self.class_at(&pos, big) != CharClass::Whitespace {
+                break;
+            }
+        }
+        let class = self.class_at(&pos, big);
+        while let Some(prev) = self.prev_position(&pos) {
+            if prev.y != pos.y || self.class_at(&prev, big) != class {
+                break;
+            }
+            pos = prev;
+        }
+        pos
+    }
+
+    /// Last character of the current or next word, like vim's `e` (or `E` with `big`).
+    pub fn word_end(&self, pos: &Position, big: bool) -> Position {
+        let mut pos = *pos;
+        loop {
+            match self.next_position(&pos) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+            if self.class_at(&pos, big) != CharClass::Whitespace {
+                break;
+            }
+        }
+        let class = self.class_at(&pos, big);
+        while let Some(next) = self.next_position(&pos) {
+            if next.y != pos.y || self.class_at(&next, big) != class {
+                break;
+            }
+            pos = next;
+        }
+        pos
+    }
+
+    /// The position after `pos`, where the end of a row counts as a position of its own.
+    pub fn next_position(&self, pos: &Position) -> Option<Position> {
+        let len = self.rows.get(pos.y)?.len();
+        if pos.x < len {
+            Some(Position { x: pos.x + 1, y: pos.y })
+        } else if pos.y + 1 < self.len() {
+            Some(Position { x: 0, y: pos.y + 1 })
+        } else {
+            None
+        }
+    }
+
+    pub fn prev_position(&self, pos: &Position) -> Option<Position> {
+        if pos.x > 0 {
+            Some(Position { x: pos.x - 1, y: pos.y })
+        } else if pos.y > 0 {
+            let y = pos.y - 1;
+            Some(Position { x: self.rows.get(y)?.len(), y })
+        } else {
+            None
+        }
+    }
+
+    /// The class of the grapheme at `pos`. With `big`, punctuation counts as part of words.
+    fn class_at(&self, pos: &Position, big: bool) -> CharClass {
+        let class = self
+            .rows
+            .get(pos.y)
+            .and_then(|row| row.grapheme(pos.x))
+            .map_or(CharClass::Whitespace, CharClass::from);
+        if big && class == CharClass::Punctuation {
+            CharClass::Word
+        } else {
+            class
+        }
+    }
+
+    /// The end of the word that ends after `pos`, by Unicode word boundaries, or of the WORD of
+    /// non-blank characters with `big`. The end of a row is a stop of its own.
+    pub fn next_word_boundary(&self, pos: &Position, big: bool) -> Position {
+        let Some(row) = self.rows.get(pos.y) else {
+            return *pos;
+        };
+        match row.words(big).into_iter().find(|word| word.end > pos.x) {
+            Some(word) => Position { x: word.end, y: pos.y },
+            None if pos.x < row.len() || pos.y + 1 >= self.len() => Position { x: row.len(), y: pos.y },
+            None => Position { x: 0, y: pos.y + 1 },
+        }
+    }
+
+    /// The start of the word that starts before `pos`, by Unicode word boundaries, or of the WORD
+    /// of non-blank characters with `big`. The start of a row is a stop of its own.
+    pub fn prev_word_boundary(&self, pos: &Position, big: bool) -> Position {
+        let Some(row) = self.rows.get(pos.y) else {
+            return *pos;
+        };
+        match row.words(big).into_iter().rev().find(|word| word.start < pos.x) {
+            Some(word) => Position { x: word.start, y: pos.y },
+            None if pos.x > 0 || pos.y == 0 => Position { x: 0, y: pos.y },
+            None => Position { x: self.rows[pos.y - 1].len(), y: pos.y - 1 },
+        }
+    }
+
+    /// Makes the next change start a new undo step, which returns the cursor to `cursor`.
+    pub fn checkpoint(&mut self, cursor: Position) {
+        self.history.checkpoint(cursor);
+    }
+
+    /// Reverts the last undo step and returns the cursor position it started at.
+    pub fn undo(&mut self, cursor: Position) -> Option<Position> {
+        let restored = self.history.undo(&mut self.rows, cursor)?;
+        self.changed = true;
+        self.highlight();
+        Some(restored)
+    }
+
+    /// Applies the last undone step again and returns the cursor position it was undone at.
+    pub fn redo(&mut self, cursor: Position) -> Option<Position> {
+        let restored = self.history.redo(&mut self.rows, cursor)?;
+        self.changed = true;
+        self.highlight();
+        Some(restored)
+    }
+
+    /// Saves the rows `first..first + old_len` for undo before they are replaced by `new_len` rows.
+    fn record(&mut self, first: usize, old_len: usize, new_len: usize) {
+        let old = self.rows[first..first + old_len].to_vec();
+        self.history.record(first, old, new_len);
+    }
+
+    pub fn highlight(&mut self) {
         for row in &mut self.rows {
-            row.highlight(&self.file_type.highlighting_options(), word);
+            row.highlight(self.file_type.highlighting_options());
         }
     }
+
+    /// Returns the positions of all matches of `query`, in document order.
+    pub fn find_all(&self, query: &str, ignore_case: bool) -> Vec<Position> {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.find_all(query, ignore_case).into_iter().map(move |x| Position { x, y }))
+            .collect()
+    }
+}
+
+/// Number of whitespace graphemes at the start of `row`.
+fn indentation(row: &Row) -> usize {
+    row.as_str().chars().take_while(|c| *c == ' ' || *c == '\t').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Document;
+
+    #[test]
+    fn outdent_row_keeps_indentation_after_the_limit() {
+        let mut document = Document::from_text("test.rs", "    foo\n\tbar\n  baz\n");
+        assert_eq!(document.outdent_row(0, "    ", 0), 0);
+        assert_eq!(document.outdent_row(1, "    ", 0), 0);
+        assert_eq!(document.outdent_row(2, "    ", 1), 1);
+        assert_eq!(document.rows_text(0, 2), "    foo\n\tbar\n baz\n");
+    }
 }
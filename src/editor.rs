@@ -1,16 +1,47 @@
+use crate::clipboard;
+use crate::Command;
+use crate::Config;
 use crate::Document;
+use crate::Explorer;
+use crate::Finder;
+use crate::HistoryKind;
+use crate::Indent;
+use crate::InputHistory;
+use crate::PromptInput;
+use crate::keys;
+use crate::paths;
+use crate::project;
+use crate::project_search;
+use crate::ProjectSearch;
+use crate::Key;
 use crate::Row;
 use crate::Terminal;
+use crate::terminal::Input;
+use crate::highlighting::Overlay;
+use crate::mode::{Action, InsertAt, Mode, Motion, Operator, Pending};
 
 use termion::color;
-use termion::event::Key;
-use std::io::Error;
+use unicode_segmentation::UnicodeSegmentation;
+use std::io::{Error, ErrorKind};
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, VecDeque};
 use std::env;
+use std::fs;
+use std::iter;
+use std::mem;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// How often the explorer checks whether the listed directories changed, while no key is pressed.
+const EXPLORER_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the results of a running project search are taken in, while no key is pressed.
+const PROJECT_SEARCH_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// Macro playback stops when this many keys are queued, which only happens if a macro plays itself.
+const MAX_QUEUED_KEYS: usize = 100_000;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum SearchDirection {
@@ -18,12 +49,59 @@ pub enum SearchDirection {
     Backward
 }
 
-#[derive(Default, Clone)]
+/// How searches treat case. Smart case ignores it unless the query has an uppercase letter.
+#[derive(PartialEq, Clone, Copy)]
+enum SearchCase {
+    Sensitive,
+    Insensitive,
+    Smart,
+}
+
+impl SearchCase {
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            Self::Sensitive => false,
+            Self::Insensitive => true,
+            Self::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::Sensitive => Self::Insensitive,
+            Self::Insensitive => Self::Smart,
+            Self::Smart => Self::Sensitive,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sensitive => "match case",
+            Self::Insensitive => "ignore case",
+            Self::Smart => "smart case",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Position {
+    /// Positions are ordered as they appear in the document: by row first, then by column.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -38,6 +116,68 @@ impl StatusMessage {
     }
 }
 
+/// Text that was deleted or yanked in normal or visual mode.
+#[derive(Default, Clone)]
+struct Register {
+    text: String,
+    /// Whether the text consists of whole rows, which are pasted as rows of their own.
+    linewise: bool,
+    /// Whether the text is a block, whose lines are pasted one below the other at the same column.
+    block: bool,
+}
+
+/// A cursor besides the main one, with the anchor of its selection.
+#[derive(Clone, Copy, PartialEq)]
+struct Cursor {
+    position: Position,
+    anchor: Option<Position>,
+}
+
+impl Cursor {
+    /// Where the selection of the cursor starts, or the cursor itself without one.
+    fn start(&self) -> Position {
+        self.anchor.map_or(self.position, |anchor| cmp::min(anchor, self.position))
+    }
+
+    fn end(&self) -> Position {
+        self.anchor.map_or(self.position, |anchor| cmp::max(anchor, self.position))
+    }
+
+    /// Whether both cursors would edit the same text: they are at the same position, their
+    /// selections share some text, or one of them is within the selection of the other.
+    fn overlaps(&self, other: &Self) -> bool {
+        match (self.anchor, other.anchor) {
+            (None, None) => self.position == other.position,
+            (Some(_), None) => self.start() < other.position && other.position < self.end(),
+            (None, Some(_)) => other.start() < self.position && self.position < other.end(),
+            (Some(_), Some(_)) => cmp::max(self.start(), other.start()) < cmp::min(self.end(), other.end()),
+        }
+    }
+
+    /// This cursor with its selection grown to cover the one of `other`, facing the same way.
+    fn merge(&self, other: &Self) -> Self {
+        let (start, end) = (cmp::min(self.start(), other.start()), cmp::max(self.end(), other.end()));
+        if start == end {
+            return *self;
+        }
+        if self.anchor.is_some_and(|anchor| anchor > self.position) {
+            Self { position: start, anchor: Some(end) }
+        } else {
+            Self { position: end, anchor: Some(start) }
+        }
+    }
+}
+
+/// A document that is open but not shown, with where its cursor was left.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    explorer: Option<Explorer>,
+    project_search: Option<ProjectSearch>,
+}
+
 pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
@@ -45,13 +185,63 @@ pub struct Editor {
     offset: Position,
     document: Document,
     status_message: StatusMessage,
+    config: Config,
+    mode: Mode,
+    pending: Pending,
+    selection_anchor: Option<Position>,
+    /// Whether the selection is a block of the same display columns in every row, rather than
+    /// the text between the anchor and the cursor.
+    block_selection: bool,
+    register: Register,
+    /// The text last copied to the system clipboard, to tell when another program replaced it.
+    clipboard: Option<String>,
+    /// Keys of the change being typed, which become `last_change` once it is complete.
+    change_keys: Vec<Key>,
+    /// Whether the current insert session started with a change that `.` should repeat.
+    recording_insert: bool,
+    last_change: Vec<Key>,
+    macros: HashMap<char, Vec<Key>>,
+    /// The register and keys of the macro being recorded.
+    recording: Option<(char, Vec<Key>)>,
+    last_macro: Option<char>,
+    /// Keys of macros being played, which are read before any key from the terminal.
+    queued_keys: VecDeque<Key>,
+    /// The display column vertical moves keep to, and the position they left the cursor at.
+    /// Moving the cursor anywhere else makes the next vertical move start from its column.
+    sticky_column: Option<(usize, Position)>,
+    /// The character typed by the last key, which decides if the next one joins its undo step.
+    last_typed: Option<char>,
+    /// The last search query and direction, which `n` and Alt-n search for again.
+    last_search: Option<(String, SearchDirection)>,
+    /// How searches treat case, switched with Alt-C in the search prompt.
+    search_case: SearchCase,
+    /// The query whose matches are highlighted, until Esc is pressed.
+    search_highlight: Option<String>,
+    input_history: InputHistory,
+    /// The column of the cursor in the message bar while a prompt is open.
+    prompt_cursor: Option<usize>,
+    /// Shown after the input of the prompt, like the match counter while searching.
+    prompt_hint: String,
+    /// Extra cursors, which edit and move along with `cursor_position` in the order they were added.
+    cursors: Vec<Cursor>,
+    /// The other open documents, in the order `bnext` goes through them.
+    buffers: Vec<Buffer>,
+    /// The project files listed below the prompt while the file finder is open.
+    finder: Option<Finder>,
+    /// The file tree the document lists, if it is the listing of a directory.
+    explorer: Option<Explorer>,
+    /// The search whose results the document lists, if it lists any.
+    project_search: Option<ProjectSearch>,
 }
 
 impl Editor {
     pub fn run(&mut self) {
         loop {
-            if let Err(error) = self.refresh_screen() {
-                die(error);
+            // Playing a macro only redraws once it is done.
+            if self.queued_keys.is_empty() || self.should_quit {
+                if let Err(error) = self.refresh_screen() {
+                    die(error);
+                }
             }
             if self.should_quit {
                 break;
@@ -64,27 +254,66 @@ impl Editor {
 
     pub fn default() -> Self {
         let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
-            let doc = Document::open(&file_name);
-            if doc.is_ok() {
-                doc.unwrap()
+        let mut initial_status = String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-P = command | Ctrl-Q = quit");
+        let mut target = None;
+        let buffer = if args.len() > 1 {
+            let (file_name, line, column) = split_file_position(&args[1]);
+            target = line.map(|line| Position {
+                x: column.unwrap_or(1).saturating_sub(1),
+                y: line.saturating_sub(1),
+            });
+            let buffer = if Path::new(file_name).is_dir() {
+                explorer_buffer(file_name)
             } else {
-                initial_status = format!("Err: Couldn't open document");
-                Document::default()
-            }
+                Document::open(file_name).map(|document| Buffer { document, ..Buffer::default() })
+            };
+            buffer.unwrap_or_else(|_| {
+                initial_status = "Err: Couldn't open document".to_string();
+                Buffer::default()
+            })
         } else {
-            Document::default()
+            Buffer::default()
         };
-        Self { 
+        let config = Config::load();
+        let mut editor = Self {
             should_quit: false,
             terminal: Terminal::default().expect("Failed to initialize terminal"),
             cursor_position: Position::default(),
             offset: Position::default(),
-            document,
+            document: buffer.document,
             status_message: StatusMessage::from(initial_status),
-         }
+            mode: if config.modal { Mode::Normal } else { Mode::Insert },
+            pending: Pending::default(),
+            selection_anchor: None,
+            block_selection: false,
+            register: Register::default(),
+            clipboard: None,
+            change_keys: Vec::new(),
+            recording_insert: false,
+            last_change: Vec::new(),
+            macros: config.macros.clone(),
+            recording: None,
+            last_macro: None,
+            queued_keys: VecDeque::new(),
+            sticky_column: None,
+            last_typed: None,
+            last_search: None,
+            search_case: SearchCase::Sensitive,
+            search_highlight: None,
+            input_history: InputHistory::load(),
+            prompt_cursor: None,
+            prompt_hint: String::new(),
+            cursors: Vec::new(),
+            buffers: Vec::new(),
+            finder: None,
+            explorer: buffer.explorer,
+            project_search: None,
+            config,
+        };
+        if let Some(target) = target {
+            editor.go_to(target);
+        }
+        editor
     }
 
     fn refresh_screen(&self) -> Result<(), std::io::Error> {
@@ -92,15 +321,29 @@ impl Editor {
         Terminal::cursor_position(&Position::default());
         if self.should_quit {
             Terminal::clear_screen();
+            Terminal::disable_bracketed_paste();
+            if self.config.modal {
+                Terminal::cursor_default_shape();
+            }
             println!("Goodbye.\r");
         } else {
             self.draw_rows();
             self.draw_status_bar();
             self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+            Terminal::cursor_position(&match self.prompt_cursor {
+                Some(x) => Position { x, y: self.terminal.size().height as usize },
+                None => Position {
+                    x: self.cursor_column().saturating_sub(self.offset.x),
+                    y: self.cursor_position.y.saturating_sub(self.offset.y),
+                },
             });
+            if self.config.modal {
+                if self.mode == Mode::Insert {
+                    Terminal::cursor_bar();
+                } else {
+                    Terminal::cursor_block();
+                }
+            }
         }
         Terminal::cursor_show();
         Terminal::flush()
@@ -108,7 +351,7 @@ impl Editor {
 
     fn save(&mut self) {
         if self.document.file_name.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
+            let new_name = self.path_prompt("Save as: ").unwrap_or(None);
             if new_name.is_none() {
                 self.status_message = StatusMessage::from("Save aborted.".to_string());
                 return;
@@ -124,50 +367,336 @@ impl Editor {
     }
 
     fn search(&mut self) {
-        let old_position = self.cursor_position.clone();
+        let old_position = self.cursor_position;
         let mut dir = SearchDirection::Forward;
-        let query = self.prompt("Search: ", |editor, key, query| {
-            let mut moved = false;
+        self.prompt_hint = format!("  [{}, Alt-C to change]", self.search_case.name());
+        let query = self.prompt_with_history("Search: ", HistoryKind::Search, |editor, key, query| {
+            let mut from = editor.cursor_position;
             match key {
-                Key::Down => {
+                Key::Alt('n') => {
                     dir = SearchDirection::Forward;
-                    editor.move_cursor(Key::Right);
-                    moved = true;
+                    from = editor.document.next_position(&from).unwrap_or(from);
                 },
-                Key::Up => {
+                Key::Alt('N') => {
                     dir = SearchDirection::Backward
                 }
+                Key::Alt('c') => editor.search_case = editor.search_case.next(),
                 _ => dir = SearchDirection::Forward,
             }
-            if let Some(position) = editor.document.find(&query, &editor.cursor_position, dir) {
-                editor.cursor_position = position;
-                editor.scroll();
-            } else if moved {
-                editor.move_cursor(Key::Left);
-            }
-            editor.document.highlight(Some(query));
+            editor.search_highlight = Some(query.clone()).filter(|query| !query.is_empty());
+            let case = editor.search_case.name();
+            editor.prompt_hint = if query.is_empty() {
+                format!("  [{case}, Alt-C to change]")
+            } else {
+                format!("  [{}, {case}]", editor.find_next(query, from, dir))
+            };
         }).unwrap_or(None);
-        if query.is_none() {
-            self.cursor_position = old_position;
-            self.scroll();
+        match query {
+            Some(query) => self.last_search = Some((query, dir)),
+            None => {
+                self.cursor_position = old_position;
+                self.search_highlight = None;
+                self.scroll();
+            }
         }
-        self.document.highlight(None)
+    }
+
+    /// Repeats the last search in its direction, or with `reverse` in the opposite one.
+    fn search_next(&mut self, reverse: bool) {
+        let Some((query, dir)) = self.last_search.clone() else {
+            self.status_message = StatusMessage::from("No previous search.".to_string());
+            return;
+        };
+        let forward = (dir == SearchDirection::Forward) != reverse;
+        let (dir, from) = if forward {
+            let pos = self.cursor_position;
+            (SearchDirection::Forward, self.document.next_position(&pos).unwrap_or(pos))
+        } else {
+            (SearchDirection::Backward, self.cursor_position)
+        };
+        self.search_highlight = Some(query.clone());
+        let message = self.find_next(&query, from, dir);
+        self.status_message = StatusMessage::from(format!("/{query}: {message}"));
+    }
+
+    /// Moves the cursor to the next match of `query` from `from` in `dir`, wrapping around the
+    /// document. Returns which match it is, like "match 3 of 17".
+    fn find_next(&mut self, query: &str, from: Position, dir: SearchDirection) -> String {
+        let ignore_case = self.search_case.ignores_case(query);
+        let mut wrapped = false;
+        let found = self.document.find(query, &from, dir, ignore_case).or_else(|| {
+            wrapped = true;
+            let start = match dir {
+                SearchDirection::Forward => Position::default(),
+                SearchDirection::Backward => {
+                    let y = self.document.len().saturating_sub(1);
+                    Position { x: self.document.row(y).map_or(0, Row::len), y }
+                }
+            };
+            self.document.find(query, &start, dir, ignore_case)
+        });
+        let Some(position) = found else {
+            return "no matches".to_string();
+        };
+        self.cursor_position = position;
+        self.scroll();
+        let matches = self.document.find_all(query, ignore_case);
+        let index = matches.iter().position(|pos| *pos == position).map_or(0, |idx| idx + 1);
+        let notice = if wrapped { ", search wrapped" } else { "" };
+        format!("match {index} of {}{notice}", matches.len())
     }
 
     fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
+        // Listings are kept up to date while waiting for keys.
+        let timeout = if self.project_search.as_ref().is_some_and(ProjectSearch::is_running) {
+            Some(PROJECT_SEARCH_UPDATE_INTERVAL)
+        } else {
+            self.explorer.is_some().then_some(EXPLORER_REFRESH_INTERVAL)
+        };
+        let is_listing = self.explorer.is_some() || self.project_search.is_some();
+        match self.poll_input(timeout)? {
+            Some(Input::Key(pressed_key)) => self.handle_key(pressed_key),
+            Some(Input::Paste(text)) if !is_listing => self.paste_text(&text),
+            _ => (),
+        }
+        self.refresh_outdated_explorer();
+        self.update_project_search();
+        Ok(())
+    }
+
+    fn read_input(&mut self) -> Result<Input, std::io::Error> {
+        loop {
+            if let Some(input) = self.poll_input(None)? {
+                return Ok(input);
+            }
+        }
+    }
+
+    /// Reads the next key of a playing macro, or else from the terminal, recording it if a macro
+    /// is being recorded. Gives up after `timeout` without input from the terminal.
+    fn poll_input(&mut self, timeout: Option<Duration>) -> Result<Option<Input>, std::io::Error> {
+        if let Some(key) = self.queued_keys.pop_front() {
+            return Ok(Some(Input::Key(key)));
+        }
+        let input = match timeout {
+            Some(timeout) => self.terminal.poll_input(timeout)?,
+            None => Some(self.terminal.read_input()?),
+        };
+        if let (Some((_, keys)), Some(input)) = (&mut self.recording, &input) {
+            match input {
+                Input::Key(key) => keys.push(*key),
+                // Played back, pasted text is typed like any other keys.
+                Input::Paste(text) => keys.extend(text.chars().map(Key::Char)),
+            }
+        }
+        Ok(input)
+    }
+
+    /// Inserts pasted text as it is, in place of the selection and without any auto-indent or
+    /// auto-pairs, as a single undo step.
+    fn paste_text(&mut self, text: &str) {
+        self.document.checkpoint(self.cursor_position);
+        self.last_typed = None;
+        self.cursors.clear();
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+            self.selection_anchor = None;
+            self.block_selection = false;
+        }
+        self.delete_selected_text();
+        self.cursor_position = self.document.insert_str(&self.cursor_position, text);
+        if self.config.modal && self.mode == Mode::Normal {
+            self.clamp_normal_cursor();
+        }
+        self.scroll();
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.stop_recording();
+            return;
+        }
+        let register = self.prompt("Record macro into register: ", |_, _, _| {}).unwrap_or(None);
+        match register.as_deref().map(str::parse::<char>) {
+            Some(Ok(register)) if register.is_ascii_alphanumeric() => self.start_recording(register),
+            Some(_) => self.status_message = StatusMessage::from("Registers are single letters or digits.".to_string()),
+            None => (),
+        }
+    }
+
+    fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some((register, mut keys)) = self.recording.take() {
+            // The last key is the one that stopped the recording.
+            keys.pop();
+            self.status_message = StatusMessage::from(format!("Recorded {} keys into register {register}.", keys.len()));
+            self.macros.insert(register, keys);
+        }
+    }
+
+    fn play_macro_prompt(&mut self) {
+        let input = self.prompt("Play macro ([count]register): ", |_, _, _| {}).unwrap_or(None);
+        let Some(input) = input else {
+            return;
+        };
+        let digits = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+        let times = input[..digits].parse().unwrap_or(1);
+        match input[digits..].parse::<char>() {
+            Ok(register) => self.play_macro(register, times),
+            Err(_) => self.status_message = StatusMessage::from("Registers are single letters or digits.".to_string()),
+        }
+    }
+
+    /// Queues the keys of the macro in `register` (or of the last one played, for `@`) `times`
+    /// times, ahead of any keys that are already queued.
+    fn play_macro(&mut self, register: char, times: usize) {
+        let register = if register == '@' { self.last_macro } else { Some(register) };
+        let Some(keys) = register.and_then(|register| self.macros.get(&register)) else {
+            self.status_message = StatusMessage::from("No macro recorded in that register.".to_string());
+            return;
+        };
+        if self.queued_keys.len().saturating_add(keys.len().saturating_mul(times)) > MAX_QUEUED_KEYS {
+            self.queued_keys.clear();
+            self.status_message = StatusMessage::from("Macro playback aborted: too many keys.".to_string());
+            return;
+        }
+        let mut queued_keys = keys.repeat(times);
+        queued_keys.extend(self.queued_keys.drain(..));
+        self.queued_keys = queued_keys.into();
+        self.last_macro = register;
+    }
+
+    fn handle_key(&mut self, pressed_key: Key) {
+        if self.explorer.is_some() && !self.handle_explorer_key(pressed_key) {
+            self.scroll();
+            return;
+        }
+        if self.project_search.is_some() && !self.handle_search_results_key(pressed_key) {
+            self.scroll();
+            return;
+        }
+        let bypasses_modes = matches!(
+            pressed_key,
+            Key::Ctrl(_) | Key::AltUp | Key::AltDown | Key::CtrlShiftUp | Key::CtrlShiftDown
+        );
+        if self.config.modal && self.mode != Mode::Insert && !bypasses_modes {
+            if pressed_key == Key::Esc {
+                self.cursors.clear();
+                self.search_highlight = None;
+            }
+            self.process_normal_key(pressed_key);
+            self.scroll();
+            return;
+        }
+        if self.config.modal && self.mode == Mode::Insert {
+            self.change_keys.push(pressed_key);
+        } else {
+            // Typing a word is undone at once, and so is a whole insert session in modal editing.
+            let joins_step = match (pressed_key, self.last_typed) {
+                (Key::Char(c), Some(last)) => c != '\n' && (!c.is_whitespace() || last.is_whitespace()),
+                _ => false,
+            };
+            if !joins_step || self.selection_anchor.is_some() {
+                self.document.checkpoint(self.cursor_position);
+            }
+        }
+        self.last_typed = match pressed_key {
+            Key::Char(c) => Some(c),
+            _ => None,
+        };
+        // Edits can leave the cursor where it was, so it no longer marks the sticky column.
+        if is_edit_key(pressed_key) {
+            self.sticky_column = None;
+        }
+        if self.block_selection && self.mode != Mode::Visual && is_edit_key(pressed_key) {
+            self.block_to_cursors();
+        }
+        if !self.cursors.is_empty() && self.handle_cursors_key(pressed_key) {
+            self.scroll();
+            return;
+        }
+        if self.mode != Mode::Visual {
+            if let Some(movement) = pressed_key.unshifted() {
+                self.selection_anchor.get_or_insert(self.cursor_position);
+                self.block_selection = pressed_key.extends_block();
+                self.move_cursor(movement);
+                self.scroll();
+                return;
+            }
+        }
+        // Deleting keys delete the selection instead, if there is one.
+        let deletes = matches!(
+            pressed_key,
+            Key::Delete | Key::Backspace | Key::Ctrl('w') | Key::CtrlDelete | Key::Alt('d')
+        );
+        if deletes && self.selection_anchor.is_some() && self.delete_selection() {
+            self.scroll();
+            return;
+        }
         match pressed_key {
+            Key::Esc if self.config.modal => self.leave_insert_mode(),
+            Key::Esc => self.search_highlight = None,
             Key::Ctrl('q') => self.attempt_quit(),
             Key::Ctrl('s') => self.save(),
             Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
-            },
+            Key::Ctrl('p') => self.command_prompt(),
+            Key::Ctrl('r') => self.toggle_recording(),
+            Key::Ctrl('e') => self.play_macro_prompt(),
+            Key::Ctrl('b') => self.jump_to_matching_bracket(),
+            Key::Ctrl('g') => self.goto_prompt(),
+            Key::Ctrl('o') => self.open_prompt(),
+            Key::Ctrl('t') => self.find_file(),
+            Key::Alt('f') => self.search_project_prompt(),
+            Key::Ctrl('v') if self.config.modal && self.mode != Mode::Insert => self.toggle_block_visual(),
+            Key::Ctrl('c') if !self.config.modal => self.copy(false),
+            Key::Ctrl('x') if !self.config.modal => self.copy(true),
+            Key::Ctrl('v') if !self.config.modal => self.paste_modeless(),
+            Key::Ctrl('n') => self.add_next_occurrence(),
+            Key::CtrlShiftUp => self.add_cursor_vertically(true),
+            Key::CtrlShiftDown => self.add_cursor_vertically(false),
+            Key::Ctrl('d') => self.duplicate_lines(),
+            Key::Ctrl('k') => self.delete_lines(),
+            Key::AltUp => self.move_lines(true),
+            Key::AltDown => self.move_lines(false),
+            Key::Alt('n') => self.search_next(false),
+            Key::Alt('N') => self.search_next(true),
+            Key::Alt('j') => {
+                let (start, end) = self.selected_rows();
+                self.join_lines(start, cmp::max(end - start, 1));
+            }
+            Key::Alt('o') => self.enter_insert_mode(InsertAt::LineBelow),
+            Key::Alt('p') => self.enter_insert_mode(InsertAt::LineAbove),
+            Key::Ctrl('z') => self.undo(),
+            Key::Ctrl('y') => self.redo(),
+            // Terminals send Ctrl-/ as Ctrl-7.
+            Key::Ctrl('7') => {
+                let (start, end) = self.selected_rows();
+                self.toggle_comment(start, end);
+            }
+            Key::Char('\t') => self.insert_tab(),
+            Key::BackTab => {
+                let (start, end) = self.selected_rows();
+                self.outdent_lines(start, end);
+            }
+            Key::Char(c) => self.type_char(c),
+            Key::Ctrl('w') => {
+                let start = self.document.prev_word_boundary(&self.cursor_position, false);
+                self.document.delete_range(&start, &self.cursor_position);
+                self.cursor_position = start;
+            }
+            Key::CtrlDelete | Key::Alt('d') => {
+                let end = self.document.next_word_boundary(&self.cursor_position, false);
+                self.document.delete_range(&self.cursor_position, &end);
+            }
             Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
+            Key::Backspace if self.cursor_position.x > 0 || self.cursor_position.y > 0 => {
+                let empty_pair = self.in_empty_pair();
+                self.move_cursor(Key::Left);
+                self.document.delete(&self.cursor_position);
+                if empty_pair {
                     self.document.delete(&self.cursor_position);
                 }
             }
@@ -178,16 +707,1093 @@ impl Editor {
             | Key::PageUp            
             | Key::PageDown            
             | Key::End            
-            | Key::Home => self.move_cursor(pressed_key),            
+            | Key::Home
+            | Key::CtrlLeft
+            | Key::CtrlRight
+            | Key::AltLeft
+            | Key::AltRight => self.move_cursor(pressed_key),            
+
+            _ => (),
+        }
+        let edits_selection = matches!(
+            pressed_key,
+            Key::Char(_)
+                | Key::BackTab
+                | Key::Backspace
+                | Key::Delete
+                | Key::Ctrl('7' | 'w' | 'd' | 'n' | 'c' | 'v')
+                | Key::CtrlDelete
+                | Key::Alt('d')
+                | Key::AltUp
+                | Key::AltDown
+        );
+        if self.mode != Mode::Visual && !edits_selection {
+            self.selection_anchor = None;
+            self.block_selection = false;
+        }
+        self.scroll();
+    }
+
+    /// Applies a key to every cursor at once, returning whether it was handled. Other keys only
+    /// apply to the main cursor, and most of them remove the extra ones first.
+    fn handle_cursors_key(&mut self, key: Key) -> bool {
+        match key {
+            _ if is_edit_key(key) => self.edit_at_cursors(key),
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::Home
+            | Key::End
+            | Key::CtrlLeft
+            | Key::CtrlRight
+            | Key::AltLeft
+            | Key::AltRight => self.move_cursors(key),
+            _ if key.unshifted().is_some() => self.move_cursors(key),
+            Key::Ctrl('s' | 'n') | Key::CtrlShiftUp | Key::CtrlShiftDown => return false,
+            _ => {
+                self.cursors.clear();
+                return false;
+            }
+        }
+        self.merge_cursors();
+        true
+    }
+
+    /// All cursors, the main one first, leaving only the main one in the editor.
+    fn take_cursors(&mut self) -> Vec<Cursor> {
+        let main = Cursor { position: self.cursor_position, anchor: self.selection_anchor.take() };
+        iter::once(main).chain(self.cursors.drain(..)).collect()
+    }
+
+    /// Puts back cursors taken with `take_cursors`.
+    fn put_cursors(&mut self, mut cursors: Vec<Cursor>) {
+        let main = cursors.remove(0);
+        self.cursor_position = main.position;
+        self.selection_anchor = main.anchor;
+        self.cursors = cursors;
+    }
+
+    /// Merges cursors that ended up at the same position, or whose selections overlap, into the
+    /// one added first, its selection growing to cover both.
+    fn merge_cursors(&mut self) {
+        let mut cursors = self.take_cursors();
+        let mut idx = 1;
+        while idx < cursors.len() {
+            if let Some(kept) = cursors[..idx].iter().position(|cursor| cursor.overlaps(&cursors[idx])) {
+                let merged = cursors.remove(idx);
+                cursors[kept] = cursors[kept].merge(&merged);
+                // The grown selection can overlap cursors it did not overlap before.
+                idx = 1;
+            } else {
+                idx += 1;
+            }
+        }
+        self.put_cursors(cursors);
+    }
+
+    /// Moves every cursor like the main one would move, extending their selections for shifted keys.
+    fn move_cursors(&mut self, key: Key) {
+        let mut cursors = self.take_cursors();
+        // The main cursor moves last, so the sticky column stays its own.
+        for cursor in cursors.iter_mut().rev() {
+            self.cursor_position = cursor.position;
+            self.selection_anchor = cursor.anchor;
+            if let Some(movement) = key.unshifted() {
+                self.selection_anchor.get_or_insert(self.cursor_position);
+                self.move_cursor(movement);
+            } else {
+                self.selection_anchor = None;
+                self.move_cursor(key);
+            }
+            *cursor = Cursor { position: self.cursor_position, anchor: self.selection_anchor };
+        }
+        self.put_cursors(cursors);
+    }
+
+    /// Types or deletes at every cursor, starting with the one furthest down the document so
+    /// that the edits do not move the cursors that are still to come.
+    fn edit_at_cursors(&mut self, key: Key) {
+        let mut cursors = self.take_cursors();
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by_key(|&i| cmp::Reverse(cursors[i].start()));
+        for (done, &i) in order.iter().enumerate() {
+            self.cursor_position = cursors[i].position;
+            self.selection_anchor = cursors[i].anchor;
+            let Some((start, end, text)) = self.cursor_edit(key) else {
+                continue;
+            };
+            self.document.delete_range(&start, &end);
+            let after = self.document.insert_str(&start, &text);
+            cursors[i] = Cursor { position: after, anchor: None };
+            for &j in &order[..done] {
+                let cursor = &mut cursors[j];
+                cursor.position = shift_position(cursor.position, end, after);
+                cursor.anchor = cursor.anchor.map(|anchor| shift_position(anchor, end, after));
+            }
+        }
+        self.put_cursors(cursors);
+    }
+
+    /// The range a key replaces at the cursor and the text it puts there. Unlike with a single
+    /// cursor, brackets are not closed automatically and a new row only keeps the indentation.
+    fn cursor_edit(&self, key: Key) -> Option<(Position, Position, String)> {
+        let pos = self.cursor_position;
+        let (start, end) = self.selection().unwrap_or((pos, pos));
+        let text = match key {
+            Key::Char('\t') => {
+                let indent_unit = self.indent_unit();
+                if indent_unit == "\t" {
+                    indent_unit
+                } else {
+                    let width = indent_unit.len();
+                    let column = self.document.row(start.y).map_or(0, |row| row.column(start.x, self.config.tab_width));
+                    " ".repeat(width - column % width)
+                }
+            }
+            Key::Char('\n') if self.config.auto_indent => {
+                let row = self.document.row(start.y).map_or("", Row::as_str);
+                let indent: String = row.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+                format!("\n{indent}")
+            }
+            Key::Char(c) => c.to_string(),
+            _ if start != end => String::new(),
+            Key::Backspace => return Some((self.document.prev_position(&pos)?, pos, String::new())),
+            Key::Delete => return Some((pos, self.document.next_position(&pos)?, String::new())),
+            Key::Ctrl('w') => return Some((self.document.prev_word_boundary(&pos, false), pos, String::new())),
+            Key::CtrlDelete | Key::Alt('d') => {
+                return Some((pos, self.document.next_word_boundary(&pos, false), String::new()));
+            }
+            _ => return None,
+        };
+        Some((start, end, text))
+    }
+
+    /// Adds a cursor on the row above the top cursor, or below the bottom one, at the same
+    /// display column.
+    fn add_cursor_vertically(&mut self, up: bool) {
+        let positions = iter::once(self.cursor_position).chain(self.cursors.iter().map(|cursor| cursor.position));
+        let edge = if up { positions.min() } else { positions.max() };
+        let Some(edge) = edge else {
+            return;
+        };
+        let y = if up {
+            edge.y.checked_sub(1)
+        } else {
+            Some(edge.y + 1).filter(|y| *y < self.document.len())
+        };
+        let Some(y) = y else {
+            return;
+        };
+        let tab_width = self.config.tab_width;
+        let column = self.document.row(edge.y).map_or(0, |row| row.column(edge.x, tab_width));
+        let x = self.document.row(y).map_or(0, |row| row.index(column, tab_width));
+        self.cursors.push(Cursor { position: Position { x, y }, anchor: None });
+        self.merge_cursors();
+    }
+
+    /// Selects the word under the cursor. With a selection, adds a cursor selecting the next
+    /// occurrence of its text after the last added cursor, wrapping around the document.
+    fn add_next_occurrence(&mut self) {
+        let Some((start, end)) = self.selection().filter(|(start, end)| start != end) else {
+            let Position { x, y } = self.cursor_position;
+            let word = self.document.row(y).and_then(|row| {
+                row.words(false)
+                    .into_iter()
+                    .find(|word| word.contains(&x) || word.end == x)
+                    .filter(|word| !row.slice(word.start, word.end).trim().is_empty())
+            });
+            if let Some(word) = word {
+                self.selection_anchor = Some(Position { x: word.start, y });
+                self.cursor_position = Position { x: word.end, y };
+            }
+            return;
+        };
+        let query = self.document.text(&start, &end);
+        if query.contains('\n') {
+            self.status_message = StatusMessage::from("Can't add cursors for text spanning rows.".to_string());
+            return;
+        }
+        let last = self.cursors.last().map_or(end, |cursor| cmp::max(cursor.position, cursor.start()));
+        let found = self
+            .document
+            .find(&query, &last, SearchDirection::Forward, false)
+            .or_else(|| self.document.find(&query, &Position::default(), SearchDirection::Forward, false));
+        let taken = |at: Position| at == start || self.cursors.iter().any(|cursor| cursor.start() == at);
+        match found.filter(|at| !taken(*at)) {
+            Some(at) => {
+                let len = query.graphemes(true).count();
+                self.cursors.push(Cursor {
+                    position: Position { x: at.x + len, y: at.y },
+                    anchor: Some(at),
+                });
+            }
+            None => self.status_message = StatusMessage::from("No more occurrences.".to_string()),
+        }
+    }
+
+    /// Starts a visual block selection, or ends it.
+    fn toggle_block_visual(&mut self) {
+        if self.mode == Mode::Visual && self.block_selection {
+            self.mode = Mode::Normal;
+            self.selection_anchor = None;
+            self.block_selection = false;
+            return;
+        }
+        if self.mode != Mode::Visual {
+            self.mode = Mode::Visual;
+            self.selection_anchor = Some(self.cursor_position);
+        }
+        self.block_selection = true;
+    }
+
+    /// The rows and display columns covered by the block selection. The columns run up to but
+    /// excluding the right edge, which in visual mode includes the character under the cursor.
+    fn block(&self) -> Option<(RangeInclusive<usize>, Range<usize>)> {
+        if !self.block_selection {
+            return None;
+        }
+        let anchor = self.selection_anchor?;
+        let cursor = self.cursor_position;
+        let tab_width = self.config.tab_width;
+        let column = |pos: Position| self.document.row(pos.y).map_or(0, |row| row.column(pos.x, tab_width));
+        let left = cmp::min(column(anchor), column(cursor));
+        let mut right = cmp::max(column(anchor), column(cursor));
+        if self.mode == Mode::Visual {
+            let after = |pos: Position| column(Position { x: pos.x + 1, y: pos.y });
+            right = cmp::max(right + 1, cmp::max(after(anchor), after(cursor)));
+        }
+        Some((cmp::min(anchor.y, cursor.y)..=cmp::max(anchor.y, cursor.y), left..right))
+    }
+
+    /// The graphemes of each row in the block selection.
+    fn block_ranges(&self) -> Vec<(usize, Range<usize>)> {
+        let Some((rows, columns)) = self.block() else {
+            return Vec::new();
+        };
+        rows.filter_map(|y| {
+            let row = self.document.row(y)?;
+            Some((y, block_range(row, &columns, self.config.tab_width)))
+        })
+        .collect()
+    }
+
+    /// The text of the block selection, one line per row.
+    fn block_text(&self) -> String {
+        let lines: Vec<&str> = self
+            .block_ranges()
+            .into_iter()
+            .filter_map(|(y, range)| Some(self.document.row(y)?.slice(range.start, range.end)))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Deletes the block selection and returns where it started in each row.
+    fn delete_block(&mut self) -> Vec<Position> {
+        let ranges = self.block_ranges();
+        for (y, range) in &ranges {
+            self.document.delete_range(&Position { x: range.start, y: *y }, &Position { x: range.end, y: *y });
+        }
+        self.selection_anchor = None;
+        self.block_selection = false;
+        ranges.into_iter().map(|(y, range)| Position { x: range.start, y }).collect()
+    }
+
+    /// Deletes, changes or yanks the visual block selection.
+    fn operate_block(&mut self, operator: Operator) {
+        let text = self.block_text();
+        let top_left = self.block_ranges().first().map(|(y, range)| Position { x: range.start, y: *y });
+        self.store(Register { text, linewise: false, block: true }, operator == Operator::Yank);
+        match operator {
+            Operator::Yank => {
+                self.selection_anchor = None;
+                self.block_selection = false;
+            }
+            Operator::Change => {
+                let starts = self.delete_block();
+                self.mode = Mode::Insert;
+                self.put_cursors(starts.into_iter().map(|position| Cursor { position, anchor: None }).collect());
+                return;
+            }
+            _ => {
+                self.delete_block();
+            }
+        }
+        self.mode = Mode::Normal;
+        if let Some(top_left) = top_left {
+            self.cursor_position = top_left;
+        }
+    }
+
+    /// Turns the block selection into a cursor on each of its rows, selecting the part of the
+    /// row within the block, so that typing and deleting apply to all rows at once.
+    fn block_to_cursors(&mut self) {
+        let mut cursors: Vec<Cursor> = self
+            .block_ranges()
+            .into_iter()
+            .map(|(y, range)| Cursor {
+                position: Position { x: range.end, y },
+                anchor: Some(Position { x: range.start, y }).filter(|_| !range.is_empty()),
+            })
+            .collect();
+        self.block_selection = false;
+        if let Some(main) = cursors.iter().position(|cursor| cursor.position.y == self.cursor_position.y) {
+            cursors.swap(0, main);
+            self.put_cursors(cursors);
+        }
+    }
+
+    /// Enters insert mode with a cursor at the left edge of the visual block in each row that
+    /// reaches it, or with `append` at its right edge in every row, padding short rows with spaces.
+    fn insert_at_block(&mut self, append: bool) {
+        let Some((_, columns)) = self.block() else {
+            return;
+        };
+        let tab_width = self.config.tab_width;
+        let mut cursors = Vec::new();
+        for (y, range) in self.block_ranges() {
+            let Some(row) = self.document.row(y) else {
+                continue;
+            };
+            let width = row.column(row.len(), tab_width);
+            let x = if append { range.end } else { range.start };
+            if append && width < columns.end {
+                let padding = " ".repeat(columns.end - width);
+                let end = self.document.insert_str(&Position { x: row.len(), y }, &padding);
+                cursors.push(Cursor { position: end, anchor: None });
+            } else if append || width > columns.start {
+                cursors.push(Cursor { position: Position { x, y }, anchor: None });
+            }
+        }
+        self.selection_anchor = None;
+        self.block_selection = false;
+        if cursors.is_empty() {
+            return;
+        }
+        self.mode = Mode::Insert;
+        self.put_cursors(cursors);
+    }
+
+    /// Pastes the lines of `text` one below the other, each at the display column of `pos`.
+    /// Rows that are too short are padded with spaces, and missing rows are added.
+    fn paste_block(&mut self, pos: Position, text: &str) {
+        let tab_width = self.config.tab_width;
+        let column = self.document.row(pos.y).map_or(0, |row| row.column(pos.x, tab_width));
+        for (i, line) in text.split('\n').enumerate() {
+            let y = pos.y + i;
+            let width = self.document.row(y).map_or(0, |row| row.column(row.len(), tab_width));
+            let len = self.document.row(y).map_or(0, Row::len);
+            if width < column {
+                self.document.insert_str(&Position { x: len, y }, &" ".repeat(column - width));
+            }
+            let x = self.document.row(y).map_or(0, |row| row.index(column, tab_width));
+            self.document.insert_str(&Position { x, y }, line);
+        }
+        self.cursor_position = self.document.clamp(&Position { x: pos.x, y: pos.y });
+    }
+
+    /// Stores deleted or yanked text in the register. Yanked text goes to the system clipboard too.
+    fn store(&mut self, register: Register, yanked: bool) {
+        if yanked {
+            self.yank(register);
+        } else {
+            self.register = register;
+        }
+    }
+
+    fn yank(&mut self, register: Register) {
+        clipboard::copy(&register.text);
+        self.clipboard = Some(register.text.clone());
+        self.register = register;
+    }
+
+    /// The text to paste: the system clipboard when another program put something else there
+    /// since the last yank, otherwise the register.
+    fn register_to_paste(&mut self) -> Register {
+        match clipboard::paste() {
+            Some(text) if !text.is_empty() && self.clipboard.as_ref() != Some(&text) => {
+                self.clipboard = Some(text.clone());
+                self.register = Register { linewise: text.ends_with('\n'), text, block: false };
+            }
+            _ => (),
+        }
+        self.register.clone()
+    }
+
+    /// Deletes the selected text or block, leaving the cursor where it started.
+    fn delete_selected_text(&mut self) {
+        if self.block_selection {
+            if let Some(start) = self.delete_block().first() {
+                self.cursor_position = *start;
+            }
+        } else {
+            self.delete_selection();
+        }
+    }
+
+    /// Copies the selection into the register, and with `cut` deletes it as well.
+    fn copy(&mut self, cut: bool) {
+        if self.block_selection {
+            let text = self.block_text();
+            self.yank(Register { text, linewise: false, block: true });
+            if cut {
+                if let Some(start) = self.delete_block().first() {
+                    self.cursor_position = *start;
+                }
+            }
+            return;
+        }
+        let Some((start, end)) = self.selection() else {
+            return;
+        };
+        let text = self.document.text(&start, &end);
+        self.yank(Register { text, linewise: false, block: false });
+        if cut {
+            self.delete_selection();
+        }
+    }
+
+    /// Pastes the register in place of the selection, leaving the cursor after the pasted text.
+    fn paste_modeless(&mut self) {
+        let Register { text, linewise, block } = self.register_to_paste();
+        self.delete_selected_text();
+        if block {
+            self.paste_block(self.cursor_position, &text);
+        } else if linewise {
+            let y = self.cursor_position.y;
+            self.document.insert_rows(y, &text);
+            self.cursor_position = Position { x: 0, y: y + text.lines().count() };
+        } else {
+            self.cursor_position = self.document.insert_str(&self.cursor_position, &text);
+        }
+    }
+
+    fn undo(&mut self) {
+        match self.document.undo(self.cursor_position) {
+            Some(cursor) => self.cursor_position = self.document.clamp(&cursor),
+            None => self.status_message = StatusMessage::from("Already at oldest change.".to_string()),
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.document.redo(self.cursor_position) {
+            Some(cursor) => self.cursor_position = self.document.clamp(&cursor),
+            None => self.status_message = StatusMessage::from("Already at newest change.".to_string()),
+        }
+    }
+
+    /// The rows covered by the selection, or else the cursor row. A selection ending at the
+    /// start of a row does not cover that row.
+    fn selected_rows(&self) -> (usize, usize) {
+        match self.selection() {
+            Some((start, end)) if end.x == 0 && end.y > start.y => (start.y, end.y - 1),
+            Some((start, end)) => (start.y, end.y),
+            None => (self.cursor_position.y, self.cursor_position.y),
+        }
+    }
+
+    fn duplicate_lines(&mut self) {
+        let (start, end) = self.selected_rows();
+        // The selection can end on the row past the last one, which is not copied.
+        let rows = end.min(self.document.len().saturating_sub(1)).saturating_sub(start) + 1;
+        if !self.document.duplicate_rows(start, end) {
+            return;
+        }
+        self.cursor_position.y += rows;
+        if let Some(anchor) = &mut self.selection_anchor {
+            anchor.y += rows;
+        }
+    }
+
+    /// Moves the selected rows, or the cursor row, along with the cursor and the selection.
+    fn move_lines(&mut self, up: bool) {
+        let (start, end) = self.selected_rows();
+        if !self.document.move_rows(start, end, up) {
+            return;
+        }
+        let shift = |pos: &mut Position| pos.y = if up { pos.y - 1 } else { pos.y + 1 };
+        shift(&mut self.cursor_position);
+        if let Some(anchor) = &mut self.selection_anchor {
+            shift(anchor);
+        }
+    }
+
+    /// Joins `joins` rows onto row `y`, leaving the cursor where the last one was joined.
+    fn join_lines(&mut self, y: usize, joins: usize) {
+        for _ in 0..joins {
+            match self.document.join_rows(y) {
+                Some(pos) => self.cursor_position = pos,
+                None => break,
+            }
+        }
+        self.selection_anchor = None;
+    }
+
+    fn delete_lines(&mut self) {
+        let (start, end) = self.selected_rows();
+        self.document.delete_rows(start, end);
+        self.selection_anchor = None;
+        self.cursor_position = self.document.clamp(&Position { x: self.cursor_position.x, y: start });
+    }
+
+    /// Toggles comments on the rows `start..=end`, keeping the cursor and the selection on the
+    /// same text.
+    fn toggle_comment(&mut self, start: usize, end: usize) {
+        if !self.edit_rows(|document| document.toggle_comment(start, end)) {
+            self.status_message = StatusMessage::from("No comment syntax for this file type.".to_string());
+        }
+    }
+
+    /// Indents the rows `start..=end` one level deeper, keeping the cursor and the selection on
+    /// the same text.
+    fn indent_lines(&mut self, start: usize, end: usize) {
+        let indent_unit = self.indent_unit();
+        self.edit_rows(|document| {
+            document.indent_rows(start, end, &indent_unit);
+            true
+        });
+    }
+
+    fn outdent_lines(&mut self, start: usize, end: usize) {
+        let indent_unit = self.indent_unit();
+        self.edit_rows(|document| {
+            document.outdent_rows(start, end, &indent_unit);
+            true
+        });
+    }
+
+    /// Applies an edit at the start of rows, then moves the cursor and the selection anchor
+    /// along with the text of their rows. Returns what `edit` returns.
+    fn edit_rows<F>(&mut self, edit: F) -> bool
+    where
+        F: FnOnce(&mut Document) -> bool,
+    {
+        let row_len = |editor: &Self, pos: Position| editor.document.row(pos.y).map_or(0, Row::len);
+        let cursor = (self.cursor_position, row_len(self, self.cursor_position));
+        let anchor = self.selection_anchor.map(|anchor| (anchor, row_len(self, anchor)));
+        if !edit(&mut self.document) {
+            return false;
+        }
+        self.cursor_position = self.follow_row_text(cursor.0, cursor.1);
+        self.selection_anchor = anchor.map(|(anchor, len)| self.follow_row_text(anchor, len));
+        true
+    }
+
+    /// Inserts spaces up to the next multiple of the indent width, or a tab when indenting with
+    /// tabs. With a selection, indents the selected rows instead.
+    fn insert_tab(&mut self) {
+        if self.selection_anchor.is_some() {
+            let (start, end) = self.selected_rows();
+            self.indent_lines(start, end);
+            return;
+        }
+        let indent_unit = self.indent_unit();
+        let text = if indent_unit == "\t" {
+            indent_unit
+        } else {
+            let width = indent_unit.len();
+            " ".repeat(width - self.cursor_column() % width)
+        };
+        self.cursor_position = self.document.insert_str(&self.cursor_position, &text);
+    }
+
+    /// Moves `pos` along with the text after the indentation of its row, which was `old_len`
+    /// graphemes long before text was inserted or removed right after the indentation.
+    fn follow_row_text(&self, pos: Position, old_len: usize) -> Position {
+        let Some(row) = self.document.row(pos.y) else {
+            return pos;
+        };
+        let indent = row.as_str().chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        if pos.x < indent {
+            return pos;
+        }
+        let x = (pos.x + row.len()).saturating_sub(old_len).max(indent);
+        Position { x: cmp::min(x, row.len()), y: pos.y }
+    }
+
+    /// Inserts a typed character in place of the selection. With `autopairs`, an opening bracket
+    /// or quote wraps the selection or is closed right away, and a closing one is typed over when
+    /// it is already there.
+    fn type_char(&mut self, c: char) {
+        let pair = if self.config.auto_pairs {
+            self.document.file_type().auto_pair(c)
+        } else {
+            None
+        };
+        if let (Some((open, close)), Some((start, end))) = (pair, self.selection()) {
+            if c == open {
+                self.document.insert(&end, close);
+                self.document.insert(&start, open);
+                let shift = |pos: Position| {
+                    if pos.y == start.y {
+                        Position { x: pos.x + 1, y: pos.y }
+                    } else {
+                        pos
+                    }
+                };
+                self.selection_anchor = self.selection_anchor.map(shift);
+                self.cursor_position = shift(self.cursor_position);
+                return;
+            }
+        }
+        self.delete_selection();
+        if c == '\n' && self.config.auto_indent {
+            let indent_unit = self.indent_unit();
+            self.cursor_position = self.document.insert_indented_newline(&self.cursor_position, &indent_unit);
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let next = self.char_at(Position { x, y });
+        if let Some((open, close)) = pair {
+            if c == close && next == Some(close) {
+                self.move_cursor(Key::Right);
+                return;
+            }
+            let next_is_free = next.is_none_or(|next| {
+                next.is_whitespace() || self.document.file_type().auto_pair(next).is_some_and(|(_, closer)| closer == next)
+            });
+            let previous = x.checked_sub(1).and_then(|x| self.char_at(Position { x, y }));
+            let inside_word = open == close && previous.is_some_and(char::is_alphanumeric);
+            if c == open && next_is_free && !inside_word {
+                self.document.insert(&self.cursor_position, close);
+                self.document.insert(&self.cursor_position, open);
+                self.move_cursor(Key::Right);
+                return;
+            }
+        }
+        if self.config.auto_indent && self.document.file_type().closes_indent(c) && self.at_indentation() {
+            let indent_unit = self.indent_unit();
+            let Position { x, y } = self.cursor_position;
+            let removed = self.document.outdent_row(y, &indent_unit, x);
+            self.cursor_position.x = x.saturating_sub(removed);
+        }
+        self.document.insert(&self.cursor_position, c);
+        self.move_cursor(Key::Right);
+    }
+
+    /// Deletes the text selected without modal editing, returning whether there was any.
+    fn delete_selection(&mut self) -> bool {
+        if self.mode == Mode::Visual {
+            return false;
+        }
+        let Some((start, end)) = self.selection() else {
+            return false;
+        };
+        self.selection_anchor = None;
+        self.document.delete_range(&start, &end);
+        self.cursor_position = start;
+        true
+    }
+
+    /// Whether the cursor sits between the two halves of an empty auto-closed pair, like `(|)`.
+    fn in_empty_pair(&self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        if !self.config.auto_pairs || x == 0 {
+            return false;
+        }
+        let (Some(before), Some(after)) = (self.char_at(Position { x: x - 1, y }), self.char_at(Position { x, y })) else {
+            return false;
+        };
+        self.document.file_type().auto_pair(before) == Some((before, after))
+    }
+
+    fn char_at(&self, pos: Position) -> Option<char> {
+        self.document
+            .row(pos.y)
+            .and_then(|row| row.grapheme(pos.x))
+            .and_then(|grapheme| grapheme.chars().next())
+    }
+
+    fn process_normal_key(&mut self, key: Key) {
+        if key == Key::Char('q') && self.recording.is_some() && self.pending.is_empty() {
+            self.stop_recording();
+            return;
+        }
+        if self.pending.is_empty() {
+            self.change_keys.clear();
+            self.document.checkpoint(self.cursor_position);
+        }
+        self.change_keys.push(key);
+        let Some((action, count)) = self.pending.feed(key, self.mode == Mode::Visual) else {
+            return;
+        };
+        let times = count.unwrap_or(1);
+        if !matches!(action, Action::Move(_)) {
+            self.sticky_column = None;
+        }
+        match action {
+            Action::Move(motion) => {
+                let column = if motion == Motion::LineEnd { usize::MAX } else { self.sticky_column() };
+                self.cursor_position = self.motion_target(motion, count);
+                if matches!(motion, Motion::Up | Motion::Down | Motion::LineEnd) {
+                    self.clamp_normal_cursor();
+                    self.sticky_column = Some((column, self.cursor_position));
+                }
+            }
+            Action::Operate(operator, motion) => self.operate(operator, motion, count),
+            Action::OperateLines(operator) => {
+                let start = self.cursor_position.y;
+                self.operate_lines(operator, start, start.saturating_add(times - 1));
+            }
+            Action::OperateSelection(operator @ (Operator::Delete | Operator::Change | Operator::Yank))
+                if self.block_selection =>
+            {
+                self.operate_block(operator);
+            }
+            Action::OperateSelection(operator) => {
+                self.block_selection = false;
+                if let Some((start, end)) = self.selection() {
+                    self.selection_anchor = None;
+                    self.mode = Mode::Normal;
+                    self.operate_range(operator, start, end);
+                }
+            }
+            Action::JoinLines => {
+                if self.mode == Mode::Visual {
+                    let (start, end) = self.selected_rows();
+                    self.mode = Mode::Normal;
+                    self.block_selection = false;
+                    self.join_lines(start, cmp::max(end - start, 1));
+                } else {
+                    self.join_lines(self.cursor_position.y, cmp::max(times - 1, 1));
+                }
+            }
+            Action::DeleteChar => {
+                let end = Position {
+                    x: self.cursor_position.x.saturating_add(times),
+                    y: self.cursor_position.y,
+                };
+                self.operate_range(Operator::Delete, self.cursor_position, end);
+            }
+            Action::Paste { before } => {
+                for _ in 0..times {
+                    self.paste(before);
+                }
+            }
+            Action::Insert(at @ (InsertAt::LineStart | InsertAt::LineEnd)) if self.block_selection => {
+                self.insert_at_block(at == InsertAt::LineEnd);
+            }
+            Action::Insert(at) => self.enter_insert_mode(at),
+            Action::ToggleVisual => {
+                if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                    self.block_selection = false;
+                } else {
+                    self.mode = Mode::Visual;
+                    self.selection_anchor = Some(self.cursor_position);
+                }
+            }
+            Action::Repeat => {
+                let keys = self.last_change.clone();
+                for _ in 0..times {
+                    for key in &keys {
+                        self.handle_key(*key);
+                    }
+                }
+                return;
+            }
+            Action::Command => self.command_prompt(),
+            Action::Search => self.search(),
+            Action::SearchNext { reverse } => self.search_next(reverse),
+            Action::Undo => {
+                for _ in 0..times {
+                    self.undo();
+                }
+            }
+            Action::Record(register) => self.start_recording(register),
+            Action::Play(register) => self.play_macro(register, times),
+        }
+        if action.is_change() {
+            if self.mode == Mode::Insert {
+                self.recording_insert = true;
+            } else {
+                self.last_change = self.change_keys.clone();
+            }
+        }
+        if self.mode != Mode::Insert {
+            self.clamp_normal_cursor();
+        }
+    }
+
+    /// In normal and visual mode the cursor stays on a character instead of after the row.
+    fn clamp_normal_cursor(&mut self) {
+        let mut position = self.document.clamp(&self.cursor_position);
+        let width = self.document.row(position.y).map_or(0, Row::len);
+        if position.x >= width {
+            position.x = width.saturating_sub(1);
+        }
+        self.cursor_position = position;
+    }
+
+    fn enter_insert_mode(&mut self, at: InsertAt) {
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        match at {
+            InsertAt::Cursor => (),
+            InsertAt::After => self.cursor_position.x = cmp::min(x.saturating_add(1), width),
+            InsertAt::LineStart => self.cursor_position.x = 0,
+            InsertAt::LineEnd => self.cursor_position.x = width,
+            InsertAt::LineBelow if self.config.auto_indent && y < self.document.len() => {
+                let indent_unit = self.indent_unit();
+                let end = Position { x: width, y };
+                self.cursor_position = self.document.insert_indented_newline(&end, &indent_unit);
+            }
+            InsertAt::LineBelow => {
+                let y = cmp::min(y.saturating_add(1), self.document.len());
+                self.document.insert_rows(y, "\n");
+                self.cursor_position = Position { x: 0, y };
+            }
+            InsertAt::LineAbove => {
+                let indent = match self.document.row(y) {
+                    Some(row) if self.config.auto_indent => {
+                        row.as_str().chars().take_while(|c| *c == ' ' || *c == '\t').collect()
+                    }
+                    _ => String::new(),
+                };
+                self.document.insert_rows(y, &format!("{indent}\n"));
+                self.cursor_position = Position { x: indent.chars().count(), y };
+            }
+        }
+        self.mode = Mode::Insert;
+    }
+
+    fn leave_insert_mode(&mut self) {
+        self.mode = Mode::Normal;
+        self.selection_anchor = None;
+        if self.recording_insert {
+            self.recording_insert = false;
+            self.last_change = self.change_keys.clone();
+        }
+        self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+        self.clamp_normal_cursor();
+    }
+
+    /// Where `motion` moves the cursor, repeated `count` times. `gg` and `G` treat the count as
+    /// a line number instead.
+    fn motion_target(&self, motion: Motion, count: Option<usize>) -> Position {
+        let times = count.unwrap_or(1);
+        let last_row = self.document.len().saturating_sub(1);
+        let mut pos = self.cursor_position;
+        match motion {
+            Motion::Left => pos.x = pos.x.saturating_sub(times),
+            Motion::Right => pos.x = pos.x.saturating_add(times),
+            Motion::Up | Motion::Down => {
+                pos.y = if motion == Motion::Up {
+                    pos.y.saturating_sub(times)
+                } else {
+                    cmp::min(pos.y.saturating_add(times), last_row)
+                };
+                let column = self.sticky_column();
+                pos.x = self.document.row(pos.y).map_or(0, |row| row.index(column, self.config.tab_width));
+            }
+            Motion::WordStart | Motion::BigWordStart => {
+                for _ in 0..times {
+                    pos = self.document.next_word_start(&pos, motion == Motion::BigWordStart);
+                }
+            }
+            Motion::WordBackward | Motion::BigWordBackward => {
+                for _ in 0..times {
+                    pos = self.document.prev_word_start(&pos, motion == Motion::BigWordBackward);
+                }
+            }
+            Motion::WordEnd | Motion::BigWordEnd => {
+                for _ in 0..times {
+                    pos = self.document.word_end(&pos, motion == Motion::BigWordEnd);
+                }
+            }
+            Motion::MatchingBracket => {
+                let row_brackets = self.document.row(pos.y).map(Row::brackets).unwrap_or_default();
+                if let Some((x, _)) = row_brackets.into_iter().find(|(x, _)| *x >= pos.x) {
+                    pos = self.document.matching_bracket(&Position { x, y: pos.y }).unwrap_or(pos);
+                }
+            }
+            Motion::LineStart => pos.x = 0,
+            Motion::LineEnd => {
+                pos.y = cmp::min(pos.y.saturating_add(times - 1), last_row);
+                pos.x = self.document.row(pos.y).map_or(0, Row::len).saturating_sub(1);
+            }
+            Motion::FirstLine => pos = Position { x: 0, y: cmp::min(count.unwrap_or(1).saturating_sub(1), last_row) },
+            Motion::LastLine => pos = Position { x: 0, y: count.map_or(last_row, |line| cmp::min(line.saturating_sub(1), last_row)) },
+        }
+        pos
+    }
+
+    fn operate(&mut self, operator: Operator, motion: Motion, count: Option<usize>) {
+        let on_word = self
+            .document
+            .row(self.cursor_position.y)
+            .and_then(|row| row.grapheme(self.cursor_position.x))
+            .is_some_and(|grapheme| !grapheme.trim().is_empty());
+        // Like in vim, `cw` only changes up to the end of the word.
+        let motion = match motion {
+            Motion::WordStart if operator == Operator::Change && on_word => Motion::WordEnd,
+            Motion::BigWordStart if operator == Operator::Change && on_word => Motion::BigWordEnd,
+            motion => motion,
+        };
+        let target = self.motion_target(motion, count);
+        let start = cmp::min(self.cursor_position, target);
+        let mut end = cmp::max(self.cursor_position, target);
+        if motion.is_linewise() {
+            self.operate_lines(operator, start.y, end.y);
+            return;
+        }
+        if motion.is_inclusive() {
+            end.x = end.x.saturating_add(1);
+        } else if end.x == 0 && end.y > start.y {
+            // An exclusive motion to the start of a row does not include the preceding newline.
+            end.y -= 1;
+            end.x = self.document.row(end.y).map_or(0, Row::len);
+        }
+        self.operate_range(operator, start, end);
+    }
+
+    fn operate_range(&mut self, operator: Operator, start: Position, end: Position) {
+        if operator.is_linewise() {
+            let end_row = if end.x == 0 && end.y > start.y { end.y - 1 } else { end.y };
+            self.operate_lines(operator, start.y, end_row);
+            return;
+        }
+        let text = if operator == Operator::Yank {
+            self.document.text(&start, &end)
+        } else {
+            self.document.delete_range(&start, &end)
+        };
+        if !text.is_empty() {
+            self.store(Register { text, linewise: false, block: false }, operator == Operator::Yank);
+        }
+        self.cursor_position = start;
+        if operator == Operator::Change {
+            self.mode = Mode::Insert;
+        }
+    }
+
+    fn operate_lines(&mut self, operator: Operator, start: usize, end: usize) {
+        if operator.is_linewise() {
+            match operator {
+                Operator::Indent => self.indent_lines(start, end),
+                Operator::Outdent => self.outdent_lines(start, end),
+                _ => self.toggle_comment(start, end),
+            }
+            self.cursor_position = self.document.clamp(&Position { x: self.cursor_position.x, y: start });
+            return;
+        }
+        let text = self.document.rows_text(start, end);
+        if text.is_empty() {
+            return;
+        }
+        if operator != Operator::Yank {
+            self.document.delete_rows(start, end);
+        }
+        if operator == Operator::Change {
+            self.document.insert_rows(start, "\n");
+            self.mode = Mode::Insert;
+        }
+        self.store(Register { text, linewise: true, block: false }, operator == Operator::Yank);
+        self.cursor_position = Position { x: 0, y: start };
+    }
+
+    fn paste(&mut self, before: bool) {
+        let Register { text, linewise, block } = self.register_to_paste();
+        if text.is_empty() {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        if block {
+            let width = self.document.row(y).map_or(0, Row::len);
+            let x = if before { x } else { cmp::min(x.saturating_add(1), width) };
+            self.paste_block(Position { x, y }, &text);
+        } else if linewise {
+            let y = if before { y } else { cmp::min(y.saturating_add(1), self.document.len()) };
+            self.document.insert_rows(y, &text);
+            self.cursor_position = Position { x: 0, y };
+        } else {
+            let width = self.document.row(y).map_or(0, Row::len);
+            let x = if before { x } else { cmp::min(x.saturating_add(1), width) };
+            let end = self.document.insert_str(&Position { x, y }, &text);
+            self.cursor_position = Position { x: end.x.saturating_sub(1), y: end.y };
+        }
+    }
+
+    /// The selected range, from its first position up to but excluding its end. In visual mode
+    /// the character under the cursor is part of the selection.
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor?;
+        let start = cmp::min(anchor, self.cursor_position);
+        let mut end = cmp::max(anchor, self.cursor_position);
+        if self.mode == Mode::Visual {
+            if end.x < self.document.row(end.y).map_or(0, Row::len) {
+                end.x += 1;
+            } else if end.y + 1 < self.document.len() {
+                end = Position { x: 0, y: end.y + 1 };
+            }
+        }
+        Some((start, end))
+    }
 
-            _ => (),
+    /// The bracket under the cursor and its partner. Without modal editing the bracket right
+    /// before the cursor counts as well, since the cursor sits between characters.
+    fn matched_brackets(&self) -> Option<(Position, Position)> {
+        let pos = self.cursor_position;
+        if let Some(partner) = self.document.matching_bracket(&pos) {
+            return Some((pos, partner));
         }
-        self.scroll();
-        Ok(())
+        if (!self.config.modal || self.mode == Mode::Insert) && pos.x > 0 {
+            let before = Position { x: pos.x - 1, y: pos.y };
+            return self.document.matching_bracket(&before).map(|partner| (before, partner));
+        }
+        None
+    }
+
+    fn jump_to_matching_bracket(&mut self) {
+        if let Some((_, partner)) = self.matched_brackets() {
+            self.cursor_position = partner;
+        }
+    }
+
+    /// The graphemes of row `y` that are part of the selection.
+    fn selected_range(&self, y: usize) -> Option<Range<usize>> {
+        if let Some((rows, columns)) = self.block() {
+            let row = self.document.row(y).filter(|_| rows.contains(&y))?;
+            return Some(block_range(row, &columns, self.config.tab_width));
+        }
+        let (start, end) = self.selection()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y { end.x } else { usize::MAX };
+        Some(from..to)
+    }
+
+    /// One level of indentation: whatever the file is already indented with, otherwise the file
+    /// type's indent width (or `tab_width`) in spaces, or a tab without `expandtab`.
+    fn indent_unit(&self) -> String {
+        let indent = self.document.indent().unwrap_or(if self.config.expand_tab {
+            Indent::Spaces(self.document.file_type().indent_width().unwrap_or(self.config.tab_width))
+        } else {
+            Indent::Tabs
+        });
+        indent.unit()
+    }
+
+    /// Whether there is nothing but whitespace before the cursor in its row.
+    fn at_indentation(&self) -> bool {
+        self.document
+            .row(self.cursor_position.y)
+            .is_some_and(|row| row.slice(0, self.cursor_position.x).trim().is_empty())
+    }
+
+    /// Display column of the cursor, which differs from `cursor_position.x` once tabs are involved.
+    fn cursor_column(&self) -> usize {
+        self.document
+            .row(self.cursor_position.y)
+            .map_or(0, |row| row.column(self.cursor_position.x, self.config.tab_width))
     }
 
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
+        let x = self.cursor_column();
+        let y = self.cursor_position.y;
         let width = self.terminal.size().width as usize;
         let height = self.terminal.size().height as usize;
         let offset = &mut self.offset;
@@ -215,11 +1821,7 @@ impl Editor {
 
         match key {            
             Key::Up => y = y.saturating_sub(1),            
-            Key::Down => {            
-                if y < height {            
-                    y = y.saturating_add(1);            
-                }            
-            }         
+            Key::Down if y < height => y = y.saturating_add(1),
             Key::Left => {
                 if x > 0 {
                     x -= 1;
@@ -240,20 +1842,20 @@ impl Editor {
                     x = 0;
                 }  
             }   
-            Key::PageUp => {
-                y = if y > terminal_height {
-                    y - terminal_height
-                }  else {
-                    0
-                }
-            },            
+            Key::PageUp => y = y.saturating_sub(terminal_height),            
             Key::PageDown => {
                 y = if y.saturating_add(terminal_height) < height {
-                    y + terminal_height as usize
+                    y + terminal_height
                 } else {
                     height
                 }
             },            
+            Key::CtrlLeft | Key::AltLeft => {
+                Position { x, y } = self.document.prev_word_boundary(&self.cursor_position, key == Key::AltLeft);
+            }
+            Key::CtrlRight | Key::AltRight => {
+                Position { x, y } = self.document.next_word_boundary(&self.cursor_position, key == Key::AltRight);
+            }
             Key::End => x = width,       
             Key::Home => x = 0,            
             _ => (),            
@@ -264,11 +1866,27 @@ impl Editor {
         } else {
             0
         };
+        let vertical = matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown);
+        let column = self.sticky_column();
+        if vertical {
+            x = self.document.row(y).map_or(0, |row| row.index(column, self.config.tab_width));
+        }
         if x > width {
             x = width;
         }
 
-        self.cursor_position = Position { x, y }            
+        self.cursor_position = Position { x, y };
+        if vertical {
+            self.sticky_column = Some((column, self.cursor_position));
+        }
+    }
+
+    /// The display column to keep when moving vertically from the cursor.
+    fn sticky_column(&self) -> usize {
+        match self.sticky_column {
+            Some((column, at)) if at == self.cursor_position => column,
+            _ => self.cursor_column(),
+        }
     }
 
     fn draw_welcome_message(&self) {            
@@ -282,20 +1900,59 @@ impl Editor {
         println!("{}\r", welcome_message);         
     }
 
-    pub fn draw_row(&self, row: &Row) {
+    pub fn draw_row(&self, row: &Row, overlays: &[(Range<usize>, Overlay)]) {
         let width = self.terminal.size().width as usize;
         let start = self.offset.x;
         let end = self.offset.x + width;
-        let row = row.render(start, end);
+        let row = row.render(start, end, self.config.tab_width, overlays);
         println!("{}\r", row)
     }
 
     fn draw_rows(&self) {
         let height = self.terminal.size().height - 1;
+        let brackets = self.matched_brackets();
+        // The file finder lists its matches over the bottom half of the text area.
+        let finder_page = self.finder.as_ref().map_or_else(Vec::new, |finder| finder.page(height as usize / 2));
+        let list_start = height as usize - finder_page.len();
         for terminal_row in 0..height {
             Terminal::clear_current_line();
-            if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                self.draw_row(row);
+            let y = terminal_row as usize + self.offset.y;
+            if let Some((file, selected)) = finder_page.get((terminal_row as usize).wrapping_sub(list_start)) {
+                self.draw_finder_match(file, *selected);
+            } else if let Some(row) = self.document.row(y) {
+                let mut overlays = Vec::new();
+                for cursor in &self.cursors {
+                    if cursor.position.y == y {
+                        let x = cursor.position.x;
+                        overlays.push((x..x + 1, Overlay::Cursor));
+                    }
+                }
+                for cursor in &self.cursors {
+                    let Some(anchor) = cursor.anchor else {
+                        continue;
+                    };
+                    let (start, end) = (cmp::min(anchor, cursor.position), cmp::max(anchor, cursor.position));
+                    if (start.y..=end.y).contains(&y) {
+                        let from = if y == start.y { start.x } else { 0 };
+                        let to = if y == end.y { end.x } else { usize::MAX };
+                        overlays.push((from..to, Overlay::Selection));
+                    }
+                }
+                if let Some(range) = self.selected_range(y) {
+                    overlays.push((range, Overlay::Selection));
+                }
+                if let Some(query) = &self.search_highlight {
+                    let len = query.graphemes(true).count();
+                    for x in row.find_all(query, self.search_case.ignores_case(query)) {
+                        overlays.push((x..x + len, Overlay::SearchMatch));
+                    }
+                }
+                for bracket in brackets.iter().flat_map(|(bracket, partner)| [bracket, partner]) {
+                    if bracket.y == y {
+                        overlays.push((bracket.x..bracket.x + 1, Overlay::MatchingBracket));
+                    }
+                }
+                self.draw_row(row, &overlays);
             } else if self.document.is_empty() && terminal_row == height / 3 {
                 self.draw_welcome_message();                
             } else {
@@ -304,39 +1961,31 @@ impl Editor {
         }
     }
 
-    fn draw_status_bar(&self) {
-        let mut status;
+    fn draw_finder_match(&self, file: &str, selected: bool) {
         let width = self.terminal.size().width as usize;
-        let modified_indicator = if self.document.is_changed() {
-            " (modified)"
+        let row = Row::from(format!("{} {file}", if selected { '>' } else { ' ' }).as_str());
+        let end = row.index(width, self.config.tab_width);
+        let padding = width.saturating_sub(row.column(end, self.config.tab_width));
+        if selected {
+            Terminal::set_bg_color(STATUS_FG_COLOR);
+            Terminal::set_fg_color(STATUS_BG_COLOR);
         } else {
-            ""
-        };
-
-        let mut file_name = "[No Name]".to_string();
-        if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
-        }
-        status = format!("{} - {} lines", file_name, self.document.len());
-        let line_indicator = format!(
-            "{} | {}/{}",
-            self.document.file_type(),
-            self.cursor_position.y.saturating_add(1),
-            self.document.len()
-        );
-        let len = status.len() + line_indicator.len();
-        if width > len {
-            status.push_str(&" ".repeat(width - len))
+            Terminal::set_bg_color(STATUS_BG_COLOR);
+            Terminal::set_fg_color(STATUS_FG_COLOR);
         }
+        println!("{}{}\r", row.slice(0, end), " ".repeat(padding));
+        Terminal::reset_bg_color();
+        Terminal::reset_fg_color();
+    }
 
-        status = format!(
-            "{} - {} lines{}",
-            file_name,
-            self.document.len(),
-            modified_indicator
-        );
-        status.truncate(width);
+    fn draw_status_bar(&self) {
+        let width = self.terminal.size().width as usize;
+        let (left, right) = self.status_segments();
+        let len = left.chars().count() + right.chars().count();
+        let mut status = left;
+        status.push_str(&" ".repeat(width.saturating_sub(len).max(1)));
+        status.push_str(&right);
+        let status: String = status.chars().take(width).collect();
         Terminal::set_bg_color(STATUS_BG_COLOR);
         Terminal::set_fg_color(STATUS_FG_COLOR);
         println!("{}\r", status);
@@ -344,72 +1993,868 @@ impl Editor {
         Terminal::reset_fg_color();
     }
 
+    /// Expands `config.status_format` into its left- and right-aligned parts.
+    fn status_segments(&self) -> (String, String) {
+        let mut left = String::new();
+        let mut right = String::new();
+        let mut aligned_right = false;
+        let mut chars = self.config.status_format.chars();
+        while let Some(c) = chars.next() {
+            let segment = if aligned_right { &mut right } else { &mut left };
+            if c != '%' {
+                segment.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('=') => aligned_right = true,
+                Some('R') => {
+                    if let Some((register, _)) = &self.recording {
+                        segment.push_str(&format!("recording @{register} "));
+                    }
+                }
+                Some('M') => {
+                    if self.config.modal {
+                        let block = if self.mode == Mode::Visual && self.block_selection { " BLOCK" } else { "" };
+                        segment.push_str(&format!("[{}{block}] ", self.mode.name()));
+                    }
+                }
+                Some('f') => {
+                    let file_name = match &self.document.file_name {
+                        Some(name) => name.chars().take(20).collect(),
+                        None => "[No Name]".to_string(),
+                    };
+                    segment.push_str(&file_name);
+                }
+                Some('m') => {
+                    if self.document.is_changed() {
+                        segment.push_str(" (modified)");
+                    }
+                }
+                Some('t') => segment.push_str(&self.document.file_type().name()),
+                Some('l') => segment.push_str(&self.cursor_position.y.saturating_add(1).to_string()),
+                Some('c') => segment.push_str(&self.cursor_column().saturating_add(1).to_string()),
+                Some('L') => segment.push_str(&self.document.len().to_string()),
+                Some('e') => segment.push_str(self.document.encoding()),
+                Some('n') => segment.push_str(self.document.line_ending().name()),
+                Some(other) => {
+                    if other != '%' {
+                        segment.push('%');
+                    }
+                    segment.push(other);
+                }
+                None => segment.push('%'),
+            }
+        }
+        (left, right)
+    }
+
     fn draw_message_bar(&self) {
         Terminal::clear_current_line();
         let message = &self.status_message;
         if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text)
+            let text = Row::from(message.text.as_str());
+            let end = text.index(self.terminal.size().width as usize, self.config.tab_width);
+            print!("{}", text.slice(0, end))
         }
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, Key, &String) {
-        let mut res = String::new();
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, Key, &String) {
+        self.prompt_with_completion(prompt, |_| Vec::new(), None, callback)
+    }
+
+    /// A prompt whose past input is brought back with Up and Down.
+    fn prompt_with_history<C>(&mut self, prompt: &str, history: HistoryKind, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+    {
+        self.prompt_with_completion(prompt, |_| Vec::new(), Some(history), callback)
+    }
+
+    /// Like `prompt`, but Tab replaces the input with the candidates returned by `complete`,
+    /// cycling through them when pressed repeatedly.
+    fn prompt_with_completion<F, C>(
+        &mut self,
+        prompt: &str,
+        complete: F,
+        history: Option<HistoryKind>,
+        callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        F: Fn(&str) -> Vec<String>,
+        C: FnMut(&mut Self, Key, &String),
+    {
+        let input = self.read_prompt(prompt, complete, history, callback)?;
+        Ok(input.filter(|input| !input.is_empty()))
+    }
+
+    /// Reads the input of a prompt, which is `None` if it was cancelled with Esc and may be empty
+    /// otherwise.
+    fn read_prompt<F, C>(
+        &mut self,
+        prompt: &str,
+        complete: F,
+        history: Option<HistoryKind>,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        F: Fn(&str) -> Vec<String>,
+        C: FnMut(&mut Self, Key, &String),
+    {
+        let entries = history.map_or_else(Vec::new, |kind| self.input_history.entries(kind).to_vec());
+        // Past the newest entry is the input being typed, which is kept while browsing.
+        let mut history_idx = entries.len();
+        let mut draft = String::new();
+        let mut input = PromptInput::default();
+        let mut completions: Vec<String> = Vec::new();
+        let mut completion_idx = 0;
+        // Whether the hint lists the completions, which it stops doing once they are done.
+        let mut listing = false;
+        let prompt_width = Row::from(prompt).column(prompt.graphemes(true).count(), 1);
         loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, res));
+            let width = (self.terminal.size().width as usize).saturating_sub(prompt_width + 1);
+            let (visible, cursor) = input.view(width);
+            self.status_message = StatusMessage::from(format!("{}{}{}", prompt, visible, self.prompt_hint));
+            self.prompt_cursor = Some(prompt_width + cursor);
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            let key = match self.read_input()? {
+                Input::Key(key) => key,
+                Input::Paste(text) => {
+                    input.insert(&text);
+                    Key::Null
+                }
+            };
             match key {
-                Key::Backspace => {
-                    if !res.is_empty() {
-                        res.truncate(res.len() - 1);
-                    }
-                },
                 Key::Esc => {
                     self.status_message = StatusMessage::from(String::from(""));
+                    self.prompt_hint.clear();
+                    self.prompt_cursor = None;
                     return Ok(None);
                 },
                 Key::Char('\n') => {
                     break;
                 },
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        res.push(c);
+                Key::Up if history_idx > 0 => {
+                    if history_idx == entries.len() {
+                        draft = input.as_str().to_string();
                     }
+                    history_idx -= 1;
+                    input.set(&entries[history_idx]);
+                }
+                Key::Down if history_idx < entries.len() => {
+                    history_idx += 1;
+                    input.set(entries.get(history_idx).unwrap_or(&draft));
+                }
+                Key::Char('\t') => {
+                    if completions.is_empty() {
+                        completions = complete(input.as_str());
+                        completion_idx = 0;
+                    } else {
+                        completion_idx = (completion_idx + 1) % completions.len();
+                    }
+                    if completions.len() > 1 {
+                        self.prompt_hint = completion_list(input.as_str(), &completions, completion_idx);
+                        listing = true;
+                    }
+                    if let Some(completion) = completions.get(completion_idx) {
+                        input.set(completion);
+                    }
+                    // A unique completion is final, so the next Tab completes from there on.
+                    if completions.len() == 1 {
+                        completions.clear();
+                    }
+                }
+                Key::Ctrl('v') => {
+                    let text = self.register_to_paste().text;
+                    input.insert(&text);
+                }
+                _ => {
+                    input.handle_key(key);
+                }
+            }
+            if key != Key::Char('\t') {
+                completions.clear();
+                if listing {
+                    self.prompt_hint.clear();
+                    listing = false;
                 }
-                _ => (),
             }
+            let res = input.as_str().to_string();
             callback(self, key, &res);
         }
         self.status_message = StatusMessage::from(String::new());
-        if res.is_empty() {
-            return Ok(None)
+        self.prompt_hint.clear();
+        self.prompt_cursor = None;
+        let res = input.as_str().to_string();
+        if let Some(kind) = history.filter(|_| !res.is_empty()) {
+            self.input_history.add(kind, &res);
         }
         Ok(Some(res))
     }
 
+    fn goto_prompt(&mut self) {
+        let input = self
+            .prompt_with_history("Go to line[:column], +N, -N or N%: ", HistoryKind::Goto, |_, _, _| {})
+            .unwrap_or(None);
+        let Some(input) = input else {
+            return;
+        };
+        match Command::parse(&format!("goto {input}")) {
+            Ok(command) => self.execute(command),
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    /// Moves the cursor to `pos` and scrolls it into the middle of the screen.
+    fn go_to(&mut self, pos: Position) {
+        self.cursor_position = self.document.clamp(&pos);
+        if self.config.modal && self.mode != Mode::Insert {
+            self.clamp_normal_cursor();
+        }
+        let height = self.terminal.size().height as usize;
+        self.offset.y = self.cursor_position.y.saturating_sub(height / 2);
+        self.scroll();
+    }
+
+    fn command_prompt(&mut self) {
+        let input = self
+            .prompt_with_completion(":", Command::complete, Some(HistoryKind::Command), |_, _, _| {})
+            .unwrap_or(None);
+        if let Some(input) = input {
+            match Command::parse(&input) {
+                Ok(command) => self.execute(command),
+                Err(message) => self.status_message = StatusMessage::from(message),
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) {
+        match command {
+            Command::Write(file_name) => {
+                if file_name.is_some() {
+                    self.document.file_name = file_name;
+                }
+                self.save();
+            }
+            Command::Quit { force } => {
+                if force {
+                    self.should_quit = true;
+                } else if !self.document.is_changed() {
+                    self.quit_unless_buffers_changed();
+                } else {
+                    self.status_message = StatusMessage::from("File has unsaved changes, use q! to discard them.".to_string());
+                }
+            }
+            Command::WriteQuit => {
+                self.save();
+                if !self.document.is_changed() {
+                    self.quit_unless_buffers_changed();
+                }
+            }
+            Command::Edit { file_name, force } => self.open(&file_name, force),
+            Command::Find => self.find_file(),
+            Command::NextBuffer { reverse } => self.next_buffer(reverse),
+            Command::CloseBuffer { force } => self.close_buffer(force),
+            Command::ListBuffers => self.list_buffers(),
+            Command::Grep(input) => self.search_project(&input),
+            Command::Explore(dir) => {
+                let dir = dir.map_or_else(|| self.document_dir(), PathBuf::from);
+                let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir };
+                if dir.is_dir() {
+                    self.open_in_buffer(&dir.to_string_lossy());
+                } else {
+                    self.status_message = StatusMessage::from(format!("Not a directory: {}", dir.display()));
+                }
+            }
+            Command::Goto { line, column } => {
+                let y = line.resolve(self.cursor_position.y, self.document.len());
+                let x = column.map_or(0, |column| column.saturating_sub(1));
+                self.go_to(Position { x, y });
+            }
+            Command::Set { option, value } => {
+                if let Err(message) = self.config.set(&option, &value) {
+                    self.status_message = StatusMessage::from(message);
+                } else if option == "modal" {
+                    self.mode = if self.config.modal { Mode::Normal } else { Mode::Insert };
+                    self.selection_anchor = None;
+                    self.pending.clear();
+                }
+            }
+            Command::SaveMacro(register) => {
+                let message = match self.macros.get(&register) {
+                    Some(keys) => match Config::persist(&format!("macro.{register}"), &keys::format_keys(keys)) {
+                        Ok(()) => format!("Saved macro {register} to the config."),
+                        Err(error) => format!("Couldn't save macro {register}: {error}"),
+                    },
+                    None => format!("No macro recorded in register {register}."),
+                };
+                self.status_message = StatusMessage::from(message);
+            }
+            Command::Substitute { range, pattern, replacement, global } => {
+                let current = self.cursor_position.y;
+                let len = self.document.len();
+                let start = range.start.resolve(current, len);
+                let end = range.end.resolve(current, len);
+                let count = self.document.substitute(start.min(end), start.max(end), &pattern, &replacement, global);
+                self.sticky_column = None;
+                self.status_message = StatusMessage::from(format!("{count} substitutions."));
+                let width = self.document.row(self.cursor_position.y).map_or(0, Row::len);
+                self.cursor_position.x = self.cursor_position.x.min(width);
+            }
+        }
+    }
+
+    /// Prompts for a file path, completed with Tab. Relative paths start at the directory of the
+    /// current document.
+    fn path_prompt(&mut self, prompt: &str) -> Result<Option<String>, std::io::Error> {
+        let base = self.document_dir();
+        let input = self.prompt_with_completion(
+            prompt,
+            |input| paths::complete(input, &base),
+            Some(HistoryKind::File),
+            |_, _, _| {},
+        )?;
+        Ok(input.map(|input| paths::resolve(&input, &base)))
+    }
+
+    /// The directory of the current document, which is empty for the working directory.
+    fn document_dir(&self) -> PathBuf {
+        if let Some(explorer) = &self.explorer {
+            return explorer.root.clone();
+        }
+        self.document
+            .file_name
+            .as_deref()
+            .and_then(|file_name| Path::new(file_name).parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    /// Lets the user pick a file of the current project by typing parts of its path, and opens
+    /// it in a buffer of its own.
+    fn find_file(&mut self) {
+        let dir = self.document_dir();
+        let root = project::root(if dir.as_os_str().is_empty() { Path::new(".") } else { &dir });
+        let finder = Finder::new(root);
+        self.prompt_hint = format!("  [{}/{}]", finder.match_count(), finder.file_count());
+        self.finder = Some(finder);
+        let input = self
+            .read_prompt("Find file: ", |_| Vec::new(), None, |editor, key, query| {
+                let Some(finder) = &mut editor.finder else {
+                    return;
+                };
+                match key {
+                    Key::Up | Key::Ctrl('p') => finder.select(-1),
+                    Key::Down | Key::Ctrl('n') => finder.select(1),
+                    _ => finder.filter(query),
+                }
+                editor.prompt_hint = format!("  [{}/{}]", finder.match_count(), finder.file_count());
+            })
+            .unwrap_or(None);
+        let Some(finder) = self.finder.take() else {
+            return;
+        };
+        if input.is_some() {
+            match finder.selected() {
+                Some(file) => self.open_in_buffer(&paths::relative_to_cwd(&finder.root.join(file))),
+                None => self.status_message = StatusMessage::from("No matching file.".to_string()),
+            }
+        }
+    }
+
+    fn open_prompt(&mut self) {
+        if let Some(file_name) = self.path_prompt("Open: ").unwrap_or(None) {
+            self.open(&file_name, false);
+        }
+    }
+
+    /// Replaces the current document with `file_name`, or with a new document of that name if
+    /// the file does not exist yet.
+    fn open(&mut self, file_name: &str, force: bool) {
+        if self.document.is_changed() && !force {
+            self.status_message = StatusMessage::from("File has unsaved changes, use e! to discard them.".to_string());
+            return;
+        }
+        match load_buffer(file_name) {
+            Ok(buffer) => {
+                self.show_buffer(buffer);
+            }
+            Err(error) => self.status_message = StatusMessage::from(format!("Couldn't open {file_name}: {error}")),
+        }
+    }
+
+    /// Shows `file_name` in a buffer of its own, keeping the current document open in another
+    /// one, or switches to its buffer if it is open already.
+    fn open_in_buffer(&mut self, file_name: &str) {
+        if is_file(&self.document, file_name) {
+            return;
+        }
+        if let Some(idx) = self.buffers.iter().position(|buffer| is_file(&buffer.document, file_name)) {
+            self.switch_buffer(idx);
+            return;
+        }
+        let buffer = match load_buffer(file_name) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Couldn't open {file_name}: {error}"));
+                return;
+            }
+        };
+        let shown = self.show_buffer(buffer);
+        self.keep_buffer(shown);
+    }
+
+    /// Keeps a buffer that was shown before the current one, unless there is nothing to keep.
+    fn keep_buffer(&mut self, buffer: Buffer) {
+        // An empty document that was never named or changed is not worth keeping.
+        if buffer.document.file_name.is_some() || buffer.document.is_changed() || !buffer.document.is_empty() {
+            self.buffers.push(buffer);
+        }
+    }
+
+    /// Shows `buffer` instead of the current document, which is returned as a buffer.
+    fn show_buffer(&mut self, buffer: Buffer) -> Buffer {
+        let shown = Buffer {
+            document: mem::replace(&mut self.document, buffer.document),
+            cursor_position: mem::replace(&mut self.cursor_position, buffer.cursor_position),
+            offset: mem::replace(&mut self.offset, buffer.offset),
+            explorer: mem::replace(&mut self.explorer, buffer.explorer),
+            project_search: mem::replace(&mut self.project_search, buffer.project_search),
+        };
+        self.selection_anchor = None;
+        self.block_selection = false;
+        self.cursors.clear();
+        self.sticky_column = None;
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+        }
+        shown
+    }
+
+    /// Switches to the buffer at `idx`, keeping the order in which `bnext` goes through all of
+    /// them.
+    fn switch_buffer(&mut self, idx: usize) {
+        let mut after = self.buffers.split_off(idx);
+        let before = mem::take(&mut self.buffers);
+        let shown = self.show_buffer(after.remove(0));
+        self.buffers = after;
+        self.buffers.push(shown);
+        self.buffers.extend(before);
+    }
+
+    fn next_buffer(&mut self, reverse: bool) {
+        if self.buffers.is_empty() {
+            self.status_message = StatusMessage::from("No other buffers.".to_string());
+        } else {
+            self.switch_buffer(if reverse { self.buffers.len() - 1 } else { 0 });
+        }
+    }
+
+    /// Handles a key in the explorer, returning whether it is left to the usual handling, as keys
+    /// that move the cursor or open a prompt are. Keys that would edit the listing are ignored.
+    fn handle_explorer_key(&mut self, key: Key) -> bool {
+        let Some(explorer) = &self.explorer else {
+            return true;
+        };
+        let Some(entry) = explorer.entry(self.cursor_position.y) else {
+            return true;
+        };
+        let (path, is_dir, is_root) = (entry.path.clone(), entry.is_dir, self.cursor_position.y == 0);
+        let expanded = explorer.is_expanded(&path);
+        match key {
+            Key::Char('\n') if is_root => self.explore_parent(),
+            Key::Char('\n') if is_dir => self.set_expanded(&path, !expanded),
+            Key::Char('l') | Key::Right if is_dir && !expanded => self.set_expanded(&path, true),
+            Key::Char('l') | Key::Right if is_dir => self.move_cursor(Key::Down),
+            Key::Char('\n' | 'l') | Key::Right => self.open_in_buffer(&paths::relative_to_cwd(&path)),
+            Key::Char('h') | Key::Left if is_dir && expanded && !is_root => self.set_expanded(&path, false),
+            Key::Char('h') | Key::Left => {
+                let parent = path.parent().and_then(|parent| explorer.row_of(parent));
+                self.cursor_position.y = parent.unwrap_or(0);
+            }
+            Key::Char('-') | Key::Backspace => self.explore_parent(),
+            Key::Char('j') => self.move_cursor(Key::Down),
+            Key::Char('k') => self.move_cursor(Key::Up),
+            Key::Char('a') => self.explorer_create(if is_dir { &path } else { path.parent().unwrap_or(&path) }),
+            Key::Char('r') if !is_root => self.explorer_rename(&path),
+            Key::Char('d') if !is_root => self.explorer_delete(&path, is_dir),
+            Key::Char('q') => self.close_buffer(false),
+            _ if is_listing_key(key) => return true,
+            _ => (),
+        }
+        false
+    }
+
+    /// Asks for a regular expression, optionally followed by glob filters, and lists the lines
+    /// of the project files that match it in a buffer of its own.
+    fn search_project_prompt(&mut self) {
+        let input = self.prompt_with_history("Search project (-g <glob> filters files): ", HistoryKind::ProjectSearch, |_, _, _| {});
+        if let Some(input) = input.unwrap_or(None) {
+            self.search_project(&input);
+        }
+    }
+
+    fn search_project(&mut self, input: &str) {
+        let dir = self.document_dir();
+        let root = project::root(if dir.as_os_str().is_empty() { Path::new(".") } else { &dir });
+        let query = project_search::Query::parse(input);
+        let ignore_case = self.search_case.ignores_case(&query.pattern);
+        match ProjectSearch::start(root, query, ignore_case) {
+            Ok(search) => {
+                let buffer = Buffer {
+                    document: search_results_document(&search),
+                    project_search: Some(search),
+                    ..Buffer::default()
+                };
+                let shown = self.show_buffer(buffer);
+                self.keep_buffer(shown);
+            }
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    /// Lists the matches the project search found since the last update.
+    fn update_project_search(&mut self) {
+        let Some(search) = &mut self.project_search else {
+            return;
+        };
+        if search.update() {
+            self.document = search_results_document(search);
+        }
+    }
+
+    /// Handles a key in the results of a project search, returning whether it is left to the
+    /// usual handling. Like in the explorer, keys that would edit the listing are ignored.
+    fn handle_search_results_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('\n' | 'o') => {
+                let Some(search) = &self.project_search else {
+                    return true;
+                };
+                let Some(found) = search.match_at(self.cursor_position.y) else {
+                    return false;
+                };
+                let file_name = paths::relative_to_cwd(&search.root.join(&found.path));
+                let position = found.position;
+                self.open_in_buffer(&file_name);
+                if is_file(&self.document, &file_name) {
+                    self.go_to(position);
+                }
+            }
+            Key::Char('j') => self.move_cursor(Key::Down),
+            Key::Char('k') => self.move_cursor(Key::Up),
+            Key::Char('q') => self.close_buffer(false),
+            _ if is_listing_key(key) => return true,
+            _ => (),
+        }
+        false
+    }
+
+    /// Shows the listing of the explorer again, with the cursor on `select` if it is listed, or
+    /// else on the same row.
+    fn show_explorer_listing(&mut self, select: Option<&Path>) {
+        let Some(explorer) = &self.explorer else {
+            return;
+        };
+        let row = select.and_then(|path| explorer.row_of(path)).unwrap_or(self.cursor_position.y);
+        self.document = explorer_document(explorer);
+        self.cursor_position = Position { x: 0, y: row.min(self.document.len().saturating_sub(1)) };
+    }
+
+    /// Reads the directories of the explorer again if any of them changed.
+    fn refresh_outdated_explorer(&mut self) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        if explorer.is_outdated() {
+            let selected = explorer.entry(self.cursor_position.y).map(|entry| entry.path.clone());
+            explorer.refresh();
+            self.show_explorer_listing(selected.as_deref());
+        }
+    }
+
+    fn set_expanded(&mut self, path: &Path, expanded: bool) {
+        if let Some(explorer) = &mut self.explorer {
+            explorer.set_expanded(path, expanded);
+        }
+        self.show_explorer_listing(Some(path));
+    }
+
+    /// Lists the parent of the explorer's directory instead, with the directory expanded.
+    fn explore_parent(&mut self) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        let old_root = explorer.root.clone();
+        let Some(parent) = old_root.parent() else {
+            return;
+        };
+        explorer.set_expanded(&old_root, true);
+        explorer.root = parent.to_path_buf();
+        explorer.refresh();
+        self.show_explorer_listing(Some(&old_root));
+        self.scroll();
+    }
+
+    fn explorer_create(&mut self, dir: &Path) {
+        let name = self.prompt("New file (end with / for a directory): ", |_, _, _| {}).unwrap_or(None);
+        let Some(name) = name else {
+            return;
+        };
+        let path = dir.join(&name);
+        let created = if name.ends_with('/') {
+            fs::create_dir_all(&path)
+        } else {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::OpenOptions::new().write(true).create_new(true).open(&path).map(|_| ()))
+        };
+        if let Err(error) = created {
+            self.status_message = StatusMessage::from(format!("Couldn't create {name}: {error}"));
+            return;
+        }
+        // Expands the directories down to the new entry, so that it can be selected.
+        if let Some(explorer) = &mut self.explorer {
+            for ancestor in path.ancestors().skip(1).take_while(|ancestor| ancestor.starts_with(dir)) {
+                explorer.set_expanded(ancestor, true);
+            }
+        }
+        self.show_explorer_listing(Some(&path));
+    }
+
+    /// Renames or moves a file or directory, relative to the directory it is in. Documents open
+    /// from below it are saved under the new name.
+    fn explorer_rename(&mut self, path: &Path) {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let new_name = self.prompt(&format!("Rename {name} to: "), |_, _, _| {}).unwrap_or(None);
+        let (Some(new_name), Some(dir)) = (new_name, path.parent()) else {
+            return;
+        };
+        let new_path = dir.join(new_name);
+        let moved: Vec<(usize, PathBuf)> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.explorer.is_none())
+            .filter_map(|(idx, buffer)| {
+                let file_name = fs::canonicalize(buffer.document.file_name.as_deref()?).ok()?;
+                Some((idx, file_name.strip_prefix(path).ok()?.to_path_buf()))
+            })
+            .collect();
+        if let Err(error) = fs::rename(path, &new_path) {
+            self.status_message = StatusMessage::from(format!("Couldn't rename {name}: {error}"));
+            return;
+        }
+        for (idx, rest) in moved {
+            let file_name = if rest.as_os_str().is_empty() { new_path.clone() } else { new_path.join(rest) };
+            self.buffers[idx].document.file_name = Some(paths::relative_to_cwd(&file_name));
+        }
+        if let Some(explorer) = &mut self.explorer {
+            explorer.refresh();
+        }
+        self.show_explorer_listing(Some(&new_path));
+    }
+
+    fn explorer_delete(&mut self, path: &Path, is_dir: bool) {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let question = if is_dir { format!("Delete {name}/ and everything in it? (y/n)") } else { format!("Delete {name}? (y/n)") };
+        if self.prompt(&question, |_, _, _| {}).unwrap_or(None).as_deref() != Some("y") {
+            return;
+        }
+        let deleted = if is_dir { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        if let Err(error) = deleted {
+            self.status_message = StatusMessage::from(format!("Couldn't delete {name}: {error}"));
+            return;
+        }
+        if let Some(explorer) = &mut self.explorer {
+            explorer.refresh();
+        }
+        self.show_explorer_listing(None);
+    }
+
+    /// Closes the current document and shows the next buffer, or an empty document if there is
+    /// none.
+    fn close_buffer(&mut self, force: bool) {
+        if self.document.is_changed() && !force {
+            self.status_message = StatusMessage::from("File has unsaved changes, use bd! to discard them.".to_string());
+            return;
+        }
+        let next = if self.buffers.is_empty() { Buffer::default() } else { self.buffers.remove(0) };
+        self.show_buffer(next);
+    }
+
+    /// Shows the open documents in the message bar, starting with the current one.
+    fn list_buffers(&mut self) {
+        let documents = iter::once(&self.document).chain(self.buffers.iter().map(|buffer| &buffer.document));
+        let names: Vec<String> = documents
+            .enumerate()
+            .map(|(idx, document)| {
+                let name = document.file_name.as_deref().unwrap_or("[No Name]");
+                let modified = if document.is_changed() { "+" } else { "" };
+                format!("{}: {name}{modified}", idx + 1)
+            })
+            .collect();
+        self.status_message = StatusMessage::from(names.join(" | "));
+    }
+
+    /// Quits unless a document in another buffer has unsaved changes.
+    fn quit_unless_buffers_changed(&mut self) {
+        if self.buffers.iter().any(|buffer| buffer.document.is_changed()) {
+            self.status_message = StatusMessage::from("Other buffers have unsaved changes, use q! to discard them.".to_string());
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    /// Asks whether to save each document with unsaved changes, showing it first, then quits.
     fn attempt_quit(&mut self) {
-        if self.document.is_changed() {
-            loop {
-                let res = self.prompt("WARNING! File has unsaved changes. Save it? (y/n)", |_, _, _| {}).unwrap_or(None);
-                if let Some(ans) = res {
-                    if ans.eq("y") {
-                        self.save();
-                        self.should_quit = true;
-                        break;
-                    } else if ans.eq("n") {
-                        self.should_quit = true;
-                        break;
-                    }
+        loop {
+            if !self.document.is_changed() {
+                match self.buffers.iter().position(|buffer| buffer.document.is_changed()) {
+                    Some(idx) => self.switch_buffer(idx),
+                    None => break,
                 }
             }
+            let res = self.prompt("WARNING! File has unsaved changes. Save it? (y/n)", |_, _, _| {}).unwrap_or(None);
+            match res.as_deref() {
+                Some("y") => {
+                    self.save();
+                    self.close_buffer(true);
+                }
+                Some("n") => self.close_buffer(true),
+                _ => (),
+            }
         }
         self.should_quit = true;
     }
 }
 
+/// Splits a `file:line[:column]` argument, as printed by compilers, into the file name, line
+/// and column. The names of existing files are never split.
+fn split_file_position(arg: &str) -> (&str, Option<usize>, Option<usize>) {
+    if Path::new(arg).exists() {
+        return (arg, None, None);
+    }
+    let mut parts = arg.rsplitn(3, ':');
+    let (last, middle, first) = (parts.next(), parts.next(), parts.next());
+    match (first, middle.and_then(|line| line.parse().ok()), last.and_then(|col| col.parse().ok())) {
+        (Some(file_name), Some(line), Some(column)) => (file_name, Some(line), Some(column)),
+        _ => match arg.rsplit_once(':').map(|(file_name, line)| (file_name, line.parse().ok())) {
+            Some((file_name, Some(line))) => (file_name, Some(line), None),
+            _ => (arg, None, None),
+        },
+    }
+}
+
+/// Reads `file_name` into a buffer: the listing of a directory, a file, or a new document if the
+/// file does not exist yet.
+fn load_buffer(file_name: &str) -> Result<Buffer, Error> {
+    if Path::new(file_name).is_dir() {
+        return explorer_buffer(file_name);
+    }
+    let document = match Document::open(file_name) {
+        Ok(document) => document,
+        Err(error) if error.kind() == ErrorKind::NotFound => Document::new(file_name),
+        Err(error) => return Err(error),
+    };
+    Ok(Buffer { document, ..Buffer::default() })
+}
+
+fn explorer_buffer(dir: &str) -> Result<Buffer, Error> {
+    let explorer = Explorer::new(fs::canonicalize(dir)?);
+    Ok(Buffer {
+        document: explorer_document(&explorer),
+        explorer: Some(explorer),
+        ..Buffer::default()
+    })
+}
+
+/// The results of a project search, named after its query.
+fn search_results_document(search: &ProjectSearch) -> Document {
+    Document::from_text(&format!("[search: {}]", search.query), &search.text())
+}
+
+/// The listing of an explorer, named after its directory.
+fn explorer_document(explorer: &Explorer) -> Document {
+    Document::from_text(&format!("{}/", explorer.root.display()), &explorer.text())
+}
+
+/// Whether `document` is the file `file_name`, which may be written differently.
+fn is_file(document: &Document, file_name: &str) -> bool {
+    document.file_name.as_deref().is_some_and(|name| {
+        name == file_name
+            || matches!((fs::canonicalize(name), fs::canonicalize(file_name)), (Ok(a), Ok(b)) if a == b)
+    })
+}
+
+/// Lists the completions of a prompt after its input, each without the part of `input` before
+/// the word being completed, and the chosen one in brackets.
+fn completion_list(input: &str, completions: &[String], chosen: usize) -> String {
+    let start = input.rfind(['/', ' ']).map_or(0, |idx| idx + 1);
+    let names: Vec<String> = completions
+        .iter()
+        .enumerate()
+        .map(|(idx, completion)| {
+            let name = completion.get(start..).unwrap_or(completion);
+            if idx == chosen {
+                format!("[{name}]")
+            } else {
+                name.to_string()
+            }
+        })
+        .collect();
+    format!("  {}", names.join(" "))
+}
+
+/// Whether read-only listings, like the explorer, leave the key to the usual handling: it
+/// moves the cursor or opens a prompt.
+fn is_listing_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Up
+            | Key::Down
+            | Key::PageUp
+            | Key::PageDown
+            | Key::Home
+            | Key::End
+            | Key::Esc
+            | Key::Ctrl('q' | 'f' | 'p' | 'g' | 'o' | 't')
+            | Key::Alt('n' | 'N' | 'f')
+    )
+}
+
+/// Whether the key types or deletes text, which happens at every cursor at once.
+fn is_edit_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Char(_) | Key::Backspace | Key::Delete | Key::Ctrl('w') | Key::CtrlDelete | Key::Alt('d')
+    )
+}
+
+/// The graphemes of `row` that overlap the display columns `columns`. Without any columns, the
+/// empty range at the grapheme where they start.
+fn block_range(row: &Row, columns: &Range<usize>, tab_width: usize) -> Range<usize> {
+    let start = row.index(columns.start, tab_width);
+    if columns.is_empty() {
+        return start..start;
+    }
+    let end = cmp::min(row.index(columns.end - 1, tab_width) + 1, row.len());
+    start..cmp::max(start, end)
+}
+
+/// Moves `pos`, which is not before `end`, along with the text after `end` once the text up to
+/// `end` was replaced by text ending at `after`.
+fn shift_position(pos: Position, end: Position, after: Position) -> Position {
+    if pos < end {
+        after
+    } else if pos.y == end.y {
+        Position { x: after.x + pos.x - end.x, y: after.y }
+    } else {
+        Position { x: pos.x, y: pos.y - end.y + after.y }
+    }
+}
+
 fn die(e: Error) {
     Terminal::clear_screen();
+    Terminal::disable_bracketed_paste();
     panic!("{}", e);
 }
\ No newline at end of file
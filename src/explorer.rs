@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A file or directory listed by the explorer.
+pub struct Entry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    depth: usize,
+}
+
+/// A tree of the files below a directory, listed one entry per row, starting with the
+/// directory itself.
+pub struct Explorer {
+    pub root: PathBuf,
+    /// The directories whose entries are listed, besides the root.
+    expanded: HashSet<PathBuf>,
+    entries: Vec<Entry>,
+    /// When each listed directory was last modified, to notice when its entries change.
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Explorer {
+    pub fn new(root: PathBuf) -> Self {
+        let mut explorer = Self {
+            root,
+            expanded: HashSet::new(),
+            entries: Vec::new(),
+            modified: HashMap::new(),
+        };
+        explorer.refresh();
+        explorer
+    }
+
+    /// Reads the listed directories again.
+    pub fn refresh(&mut self) {
+        self.entries = vec![Entry {
+            path: self.root.clone(),
+            is_dir: true,
+            depth: 0,
+        }];
+        self.modified.clear();
+        let root = self.root.clone();
+        self.list(&root, 1);
+    }
+
+    fn list(&mut self, dir: &Path, depth: usize) {
+        self.modified.insert(dir.to_path_buf(), modified(dir));
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<(PathBuf, bool)> = entries
+            .filter_map(Result::ok)
+            .map(|entry| (entry.path(), entry.path().is_dir()))
+            .collect();
+        // Directories come first, like in most file managers.
+        entries.sort_by(|(a, a_is_dir), (b, b_is_dir)| b_is_dir.cmp(a_is_dir).then_with(|| a.cmp(b)));
+        for (path, is_dir) in entries {
+            let expanded = is_dir && self.expanded.contains(&path);
+            self.entries.push(Entry {
+                path: path.clone(),
+                is_dir,
+                depth,
+            });
+            if expanded {
+                self.list(&path, depth + 1);
+            }
+        }
+    }
+
+    /// Whether any listed directory changed since it was read.
+    pub fn is_outdated(&self) -> bool {
+        self.modified.iter().any(|(dir, time)| modified(dir) != *time)
+    }
+
+    pub fn entry(&self, row: usize) -> Option<&Entry> {
+        self.entries.get(row)
+    }
+
+    /// The row listing `path`, if it is listed.
+    pub fn row_of(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.path == path)
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        path == self.root || self.expanded.contains(path)
+    }
+
+    /// Lists the entries of the directory `path` as well, or stops listing them.
+    pub fn set_expanded(&mut self, path: &Path, expanded: bool) {
+        if expanded {
+            self.expanded.insert(path.to_path_buf());
+        } else {
+            self.expanded.remove(path);
+        }
+        self.refresh();
+    }
+
+    /// The listing: each entry indented by its depth, with directories marked by whether they
+    /// are expanded.
+    pub fn text(&self) -> String {
+        let mut text = format!("{}/\n", self.root.display());
+        for entry in &self.entries[1..] {
+            let name = entry.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            let indent = "  ".repeat(entry.depth - 1);
+            if entry.is_dir {
+                let marker = if self.is_expanded(&entry.path) { '-' } else { '+' };
+                text.push_str(&format!("{indent}{marker} {name}/\n"));
+            } else {
+                text.push_str(&format!("{indent}  {name}\n"));
+            }
+        }
+        text
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -1,6 +1,19 @@
+/// Bracket pairs, used to match closing brackets to their opening ones.
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    /// Spaces per indentation level, instead of the `tabwidth` option.
+    indent_width: Option<usize>,
+    /// Characters after which a new line is indented one level deeper.
+    indent_openers: Vec<char>,
+    /// Brackets and quotes that are closed automatically when `autopairs` is on.
+    auto_pairs: Vec<(char, char)>,
+    /// Token that comments out the rest of a line, such as `//`.
+    line_comment: Option<String>,
+    /// Tokens around a block comment, such as `/*` and `*/`.
+    block_comment: Option<(String, String)>,
 }
 
 #[derive(Default)]
@@ -18,6 +31,11 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            indent_width: None,
+            indent_openers: vec!['{', '(', '['],
+            auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+            line_comment: None,
+            block_comment: None,
         }
     }
 }
@@ -31,6 +49,40 @@ impl FileType {
         &self.hl_opts
     }
 
+    /// Returns the opening and closing bracket of the pair `c` belongs to.
+    pub fn bracket_pair(c: char) -> Option<(char, char)> {
+        BRACKETS.iter().copied().find(|(open, close)| *open == c || *close == c)
+    }
+
+    pub fn indent_width(&self) -> Option<usize> {
+        self.indent_width
+    }
+
+    /// Whether a new line after `c` should be indented one level deeper.
+    pub fn opens_indent(&self, c: char) -> bool {
+        self.indent_openers.contains(&c)
+    }
+
+    /// Whether typing `c` at the start of a row should remove one level of indentation.
+    pub fn closes_indent(&self, c: char) -> bool {
+        BRACKETS
+            .iter()
+            .any(|(open, close)| *close == c && self.opens_indent(*open))
+    }
+
+    /// Returns the auto-closed pair `c` opens or closes.
+    pub fn auto_pair(&self, c: char) -> Option<(char, char)> {
+        self.auto_pairs.iter().copied().find(|(open, close)| *open == c || *close == c)
+    }
+
+    pub fn line_comment(&self) -> Option<&str> {
+        self.line_comment.as_deref()
+    }
+
+    pub fn block_comment(&self) -> Option<(&str, &str)> {
+        self.block_comment.as_ref().map(|(open, close)| (open.as_str(), close.as_str()))
+    }
+
     pub fn from(file_name: &str) -> Self {
         if file_name.ends_with(".rs") {
             return Self {
@@ -110,6 +162,77 @@ impl FileType {
                         "f64".to_string(),            
                     ],
                 },
+                indent_width: Some(4),
+                indent_openers: vec!['{', '(', '['],
+                // No single quotes, which mostly start lifetimes.
+                auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+                line_comment: Some(String::from("//")),
+                block_comment: Some((String::from("/*"), String::from("*/"))),
+            };
+        }
+        if file_name.ends_with(".py") {
+            return Self {
+                name: String::from("Python"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    characters: false,
+                    comments: false,
+                    primary_keywords: vec![
+                        "and".to_string(),
+                        "as".to_string(),
+                        "assert".to_string(),
+                        "async".to_string(),
+                        "await".to_string(),
+                        "break".to_string(),
+                        "class".to_string(),
+                        "continue".to_string(),
+                        "def".to_string(),
+                        "del".to_string(),
+                        "elif".to_string(),
+                        "else".to_string(),
+                        "except".to_string(),
+                        "False".to_string(),
+                        "finally".to_string(),
+                        "for".to_string(),
+                        "from".to_string(),
+                        "global".to_string(),
+                        "if".to_string(),
+                        "import".to_string(),
+                        "in".to_string(),
+                        "is".to_string(),
+                        "lambda".to_string(),
+                        "None".to_string(),
+                        "nonlocal".to_string(),
+                        "not".to_string(),
+                        "or".to_string(),
+                        "pass".to_string(),
+                        "raise".to_string(),
+                        "return".to_string(),
+                        "True".to_string(),
+                        "try".to_string(),
+                        "while".to_string(),
+                        "with".to_string(),
+                        "yield".to_string(),
+                    ],
+                    secondary_keywords: vec![
+                        "bool".to_string(),
+                        "bytes".to_string(),
+                        "dict".to_string(),
+                        "float".to_string(),
+                        "int".to_string(),
+                        "list".to_string(),
+                        "self".to_string(),
+                        "set".to_string(),
+                        "str".to_string(),
+                        "tuple".to_string(),
+                    ],
+                },
+                indent_width: Some(4),
+                indent_openers: vec![':', '{', '(', '['],
+                auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+                line_comment: Some(String::from("#")),
+                block_comment: None,
             };
         }
         Self::default()
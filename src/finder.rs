@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use crate::project;
+
+/// The files of a project, narrowed down and ranked by a fuzzy query.
+pub struct Finder {
+    pub root: PathBuf,
+    files: Vec<String>,
+    query: String,
+    /// Indices into `files` of the files matching the query, best first.
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Finder {
+    /// Lists the files of the project `root`.
+    pub fn new(root: PathBuf) -> Self {
+        let files = project::files(&root);
+        let matches = (0..files.len()).collect();
+        Self {
+            root,
+            files,
+            query: String::new(),
+            matches,
+            selected: 0,
+        }
+    }
+
+    /// Keeps the files that contain the characters of `query` in order, ignoring case, ranked
+    /// by how well they match. Selects the best match.
+    pub fn filter(&mut self, query: &str) {
+        if query == self.query {
+            return;
+        }
+        self.query = query.to_string();
+        let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).collect();
+        let mut scored: Vec<(i64, usize)> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, file)| score(&query, file).map(|score| (score, idx)))
+            .collect();
+        // Shorter paths win ties, then the order of the listing.
+        scored.sort_by_key(|(score, idx)| (-score, self.files[*idx].len(), *idx));
+        self.matches = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = 0;
+    }
+
+    /// Moves the selection by `delta` matches, wrapping around.
+    pub fn select(&mut self, delta: isize) {
+        let len = self.matches.len();
+        if len > 0 {
+            self.selected = self.selected.saturating_add_signed(delta.rem_euclid(len as isize)) % len;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.matches.get(self.selected).map(|idx| self.files[*idx].as_str())
+    }
+
+    /// Up to `rows` matching files around the selection, best first, with whether each is
+    /// selected.
+    pub fn page(&self, rows: usize) -> Vec<(&str, bool)> {
+        let start = (self.selected + 1).saturating_sub(rows);
+        self.matches
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(rows)
+            .map(|(position, idx)| (self.files[*idx].as_str(), position == self.selected))
+            .collect()
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+/// Scores how well `path` matches the lowercase `query`, or `None` if it does not contain its
+/// characters in order.
+///
+/// Characters are matched from the end, so that matches fall into the file name where possible.
+/// Matches in the file name, at the start of a word and right after each other score higher.
+fn score(query: &[char], path: &str) -> Option<i64> {
+    let chars: Vec<char> = path.chars().collect();
+    let name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |idx| idx + 1);
+    let mut score = 0;
+    let mut end = chars.len();
+    let mut last_match = None;
+    for query_char in query.iter().rev() {
+        let idx = chars[..end].iter().rposition(|c| c.to_lowercase().eq([*query_char]))?;
+        score += 1;
+        if idx >= name_start {
+            score += 2;
+        }
+        let prev = idx.checked_sub(1).map(|prev| chars[prev]);
+        let starts_word = match prev {
+            None => true,
+            Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && chars[idx].is_uppercase()),
+        };
+        if starts_word {
+            score += 3;
+        }
+        if last_match == Some(idx + 1) {
+            score += 4;
+        }
+        last_match = Some(idx);
+        end = idx;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    fn score(query: &str, path: &str) -> Option<i64> {
+        super::score(&query.chars().collect::<Vec<char>>(), path)
+    }
+
+    #[test]
+    fn requires_the_characters_in_order() {
+        assert!(score("ab", "ab").is_some());
+        assert!(score("ba", "ab").is_none());
+        assert!(score("ed", "src/Editor.rs").is_some());
+    }
+
+    #[test]
+    fn prefers_matches_in_the_file_name() {
+        assert!(score("ed", "src/editor.rs") > score("ed", "editor/src.rs"));
+    }
+
+    #[test]
+    fn prefers_consecutive_matches() {
+        assert!(score("ab", "ab.rs") > score("ab", "axb.rs"));
+    }
+
+    #[test]
+    fn prefers_word_starts() {
+        assert!(score("fb", "foo_bar.rs") > score("fb", "fooxbar.rs"));
+        assert!(score("fb", "fooBar.rs") > score("fb", "fooxbar.rs"));
+    }
+}
@@ -0,0 +1,104 @@
+/// Matches `path` against a glob `pattern`, as used in `.gitignore` files.
+///
+/// `*` matches any run of characters within a path segment, `?` a single one and `[...]` one of
+/// a set, like `[a-z]` or `[!0-9]`. `**` also matches across `/`, so `**/test` matches `test` in
+/// any directory and `src/**` everything below `src`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    match_from(&pattern, &path)
+}
+
+fn match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // `**/` matches whole directories, including none at all.
+            if let Some(rest) = rest.strip_prefix(&['/']) {
+                return match_from(rest, path)
+                    || (0..path.len())
+                        .filter(|idx| path[*idx] == '/')
+                        .any(|idx| match_from(rest, &path[idx + 1..]));
+            }
+            (0..=path.len()).any(|idx| match_from(rest, &path[idx..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            let segment_len = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+            (0..=segment_len).any(|idx| match_from(rest, &path[idx..]))
+        }
+        Some('?') => path.first().is_some_and(|c| *c != '/') && match_from(&pattern[1..], &path[1..]),
+        Some('[') => match (path.first(), match_class(&pattern[1..], path.first().copied())) {
+            (Some(_), Some((true, rest))) => match_from(rest, &path[1..]),
+            (_, Some((false, _))) | (None, Some(_)) => false,
+            // Without a closing bracket, `[` is an ordinary character.
+            (_, None) => path.first() == Some(&'[') && match_from(&pattern[1..], &path[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => path.first() == Some(&pattern[1]) && match_from(&pattern[2..], &path[1..]),
+        Some(c) => path.first() == Some(c) && match_from(&pattern[1..], &path[1..]),
+    }
+}
+
+/// Matches `c` against the set that starts `pattern`, returning whether it is in the set and
+/// the pattern after the closing bracket, or `None` if the set is never closed.
+fn match_class(pattern: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut idx) = match pattern.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut found = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(idx)?;
+        if start == ']' && !first {
+            break;
+        }
+        first = false;
+        if pattern.get(idx + 1) == Some(&'-') && pattern.get(idx + 2).is_some_and(|end| *end != ']') {
+            let end = pattern[idx + 2];
+            found |= c.is_some_and(|c| (start..=end).contains(&c));
+            idx += 3;
+        } else {
+            found |= c == Some(start);
+            idx += 1;
+        }
+    }
+    Some((found != negated && c != Some('/'), &pattern[idx + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn star_stays_within_a_segment() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn double_star_matches_any_directories() {
+        assert!(matches("**/test", "test"));
+        assert!(matches("**/test", "a/b/test"));
+        assert!(!matches("**/test", "a/latest"));
+        assert!(matches("src/**", "src/a/b.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+    }
+
+    #[test]
+    fn negated_class_matches_characters_outside_it() {
+        assert!(matches("[!a-z]x", "1x"));
+        assert!(!matches("[!a-z]x", "ax"));
+        assert!(!matches("a[!b]c", "a/c"));
+        assert!(!matches("[!a-z]", ""));
+    }
+
+    #[test]
+    fn unclosed_bracket_is_literal() {
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+        assert!(matches("x[", "x["));
+    }
+}
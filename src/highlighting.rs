@@ -1,10 +1,9 @@
-use termion::color;
+use termion::{color, style};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum Type {
     None,
     Number,
-    SearchResult,
     String,
     Character,
     Comment,
@@ -16,7 +15,6 @@ impl Type {
     pub fn to_color(self) -> impl color::Color {
         match self {
             Type::Number => color::Rgb(220, 163, 163),
-            Type::SearchResult => color::Rgb(38, 139, 210),
             Type::String => color::Rgb(211, 54, 130),
             Type::Character => color::Rgb(108, 113, 196),
             Type::Comment => color::Rgb(133, 153, 0),
@@ -25,4 +23,31 @@ impl Type {
             _ => color::Rgb(255, 255, 255),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Styles drawn on top of the syntax highlighting, changing the background only.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Overlay {
+    Selection,
+    MatchingBracket,
+    SearchMatch,
+    /// An extra cursor of multiple cursor editing.
+    Cursor,
+}
+
+impl Overlay {
+    pub fn start(self) -> String {
+        match self {
+            Overlay::Selection | Overlay::Cursor => format!("{}", style::Invert),
+            Overlay::MatchingBracket => format!("{}", color::Bg(color::Rgb(88, 110, 117))),
+            Overlay::SearchMatch => format!("{}", color::Bg(color::Rgb(38, 139, 210))),
+        }
+    }
+
+    pub fn end(self) -> String {
+        match self {
+            Overlay::Selection | Overlay::Cursor => format!("{}", style::NoInvert),
+            Overlay::MatchingBracket | Overlay::SearchMatch => format!("{}", color::Bg(color::Reset)),
+        }
+    }
+}
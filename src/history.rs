@@ -0,0 +1,90 @@
+use std::cmp;
+
+use crate::Position;
+use crate::Row;
+
+/// How many steps are kept for undo.
+const MAX_STEPS: usize = 1000;
+
+/// Rows `first..first + len` replaced `rows`.
+struct Change {
+    first: usize,
+    len: usize,
+    rows: Vec<Row>,
+}
+
+/// Changes that are undone together, with the cursor position to return to.
+struct Step {
+    cursor: Position,
+    changes: Vec<Change>,
+}
+
+/// The undo and redo stacks of a document. Every change stores the rows it replaced, and
+/// changes are grouped into steps by `checkpoint`.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// The cursor position the next step starts at. Until it is set, changes join the last step.
+    next_step: Option<Position>,
+}
+
+impl History {
+    /// Makes the next change start a new step, which returns the cursor to `cursor` when undone.
+    pub fn checkpoint(&mut self, cursor: Position) {
+        self.next_step = Some(cursor);
+    }
+
+    /// Records that the rows `old` starting at row `first` are replaced by `len` new rows.
+    pub fn record(&mut self, first: usize, old: Vec<Row>, len: usize) {
+        self.redo.clear();
+        let change = Change { first, len, rows: old };
+        match (self.next_step.take(), self.undo.last_mut()) {
+            (None, Some(step)) => step.changes.push(change),
+            (cursor, _) => {
+                self.undo.push(Step {
+                    cursor: cursor.unwrap_or_default(),
+                    changes: vec![change],
+                });
+                if self.undo.len() > MAX_STEPS {
+                    self.undo.remove(0);
+                }
+            }
+        }
+    }
+
+    /// Reverts the last step in `rows` and returns the cursor position it started at.
+    pub fn undo(&mut self, rows: &mut Vec<Row>, cursor: Position) -> Option<Position> {
+        let step = self.undo.pop()?;
+        let restored = step.cursor;
+        self.redo.push(apply(rows, step, cursor));
+        self.next_step = Some(restored);
+        Some(restored)
+    }
+
+    /// Applies the last undone step again and returns the cursor position it was undone at.
+    pub fn redo(&mut self, rows: &mut Vec<Row>, cursor: Position) -> Option<Position> {
+        let step = self.redo.pop()?;
+        let restored = step.cursor;
+        self.undo.push(apply(rows, step, cursor));
+        self.next_step = Some(restored);
+        Some(restored)
+    }
+}
+
+/// Reverts the changes of `step`, last one first, and returns the step that reverts them again.
+fn apply(rows: &mut Vec<Row>, step: Step, cursor: Position) -> Step {
+    let changes = step
+        .changes
+        .into_iter()
+        .rev()
+        .map(|change| {
+            let first = cmp::min(change.first, rows.len());
+            let end = cmp::min(first.saturating_add(change.len), rows.len());
+            let len = change.rows.len();
+            let replaced = rows.splice(first..end, change.rows).collect();
+            Change { first, len, rows: replaced }
+        })
+        .collect();
+    Step { cursor, changes }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// How many entries are kept per prompt.
+const MAX_ENTRIES: usize = 100;
+
+/// The prompts that remember their input.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryKind {
+    Search,
+    ProjectSearch,
+    Command,
+    File,
+    Goto,
+}
+
+impl HistoryKind {
+    const ALL: [Self; 5] = [Self::Search, Self::ProjectSearch, Self::Command, Self::File, Self::Goto];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Search => "search",
+            Self::ProjectSearch => "project-search",
+            Self::Command => "command",
+            Self::File => "file",
+            Self::Goto => "goto",
+        }
+    }
+}
+
+/// Past input of the prompts, oldest first, kept in `$XDG_STATE_HOME/hecto/history` (or
+/// `~/.local/state/hecto/history`) across sessions.
+///
+/// The file has one `<prompt>\t<input>` line per entry. Prompt input never contains tabs or
+/// newlines, so no escaping is needed.
+#[derive(Default)]
+pub struct InputHistory {
+    entries: HashMap<HistoryKind, Vec<String>>,
+}
+
+impl InputHistory {
+    /// Loads the history file, which may be missing.
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        let Some(contents) = Self::path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return history;
+        };
+        for line in contents.lines() {
+            let Some((name, entry)) = line.split_once('\t') else {
+                continue;
+            };
+            if let Some(kind) = HistoryKind::ALL.into_iter().find(|kind| kind.name() == name) {
+                history.push(kind, entry);
+            }
+        }
+        history
+    }
+
+    pub fn entries(&self, kind: HistoryKind) -> &[String] {
+        self.entries.get(&kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `entry` as the newest input of the prompt, removing an older copy of it, and saves
+    /// the history. Failing to save only loses the history, so errors are ignored.
+    pub fn add(&mut self, kind: HistoryKind, entry: &str) {
+        self.push(kind, entry);
+        let _ = self.save();
+    }
+
+    fn push(&mut self, kind: HistoryKind, entry: &str) {
+        let entries = self.entries.entry(kind).or_default();
+        entries.retain(|existing| existing != entry);
+        entries.push(entry.to_string());
+        if entries.len() > MAX_ENTRIES {
+            entries.remove(0);
+        }
+    }
+
+    fn save(&self) -> Result<(), io::Error> {
+        let path = Self::path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No state directory"))?;
+        let mut contents = String::new();
+        for kind in HistoryKind::ALL {
+            for entry in self.entries(kind) {
+                contents.push_str(&format!("{}\t{entry}\n", kind.name()));
+            }
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    }
+
+    fn path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+        };
+        Some(dir.join("hecto").join("history"))
+    }
+}
@@ -0,0 +1,231 @@
+use std::str;
+
+use termion::event;
+
+/// A key press. Mirrors `termion::event::Key`, plus the modified navigation keys that termion
+/// reports as unsupported escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    BackTab,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Alt(char),
+    Ctrl(char),
+    Null,
+    Esc,
+    ShiftLeft,
+    ShiftRight,
+    ShiftUp,
+    ShiftDown,
+    ShiftHome,
+    ShiftEnd,
+    CtrlLeft,
+    CtrlRight,
+    CtrlShiftLeft,
+    CtrlShiftRight,
+    CtrlShiftUp,
+    CtrlShiftDown,
+    AltLeft,
+    AltRight,
+    AltUp,
+    AltDown,
+    AltShiftLeft,
+    AltShiftRight,
+    AltShiftUp,
+    AltShiftDown,
+    CtrlDelete,
+}
+
+impl From<event::Key> for Key {
+    fn from(key: event::Key) -> Self {
+        match key {
+            event::Key::Backspace => Self::Backspace,
+            event::Key::Left => Self::Left,
+            event::Key::Right => Self::Right,
+            event::Key::Up => Self::Up,
+            event::Key::Down => Self::Down,
+            event::Key::Home => Self::Home,
+            event::Key::End => Self::End,
+            event::Key::PageUp => Self::PageUp,
+            event::Key::PageDown => Self::PageDown,
+            event::Key::BackTab => Self::BackTab,
+            event::Key::Delete => Self::Delete,
+            event::Key::Insert => Self::Insert,
+            event::Key::F(n) => Self::F(n),
+            event::Key::Char(c) => Self::Char(c),
+            event::Key::Alt(c) => Self::Alt(c),
+            // Many terminals send Ctrl-H for Backspace.
+            event::Key::Ctrl('h') => Self::Backspace,
+            event::Key::Ctrl(c) => Self::Ctrl(c),
+            event::Key::Esc => Self::Esc,
+            _ => Self::Null,
+        }
+    }
+}
+
+impl Key {
+    /// Parses a CSI sequence with modifiers, such as `\x1b[1;2D` for Shift-Left.
+    pub fn from_escape_sequence(bytes: &[u8]) -> Option<Self> {
+        let sequence = str::from_utf8(bytes).ok()?.strip_prefix("\x1b[")?;
+        let (params, last) = sequence.split_at(sequence.len().checked_sub(1)?);
+        let (code, modifiers) = params.split_once(';')?;
+        let key = match (code, modifiers, last) {
+            (_, "2", "D") => Self::ShiftLeft,
+            (_, "2", "C") => Self::ShiftRight,
+            (_, "2", "A") => Self::ShiftUp,
+            (_, "2", "B") => Self::ShiftDown,
+            (_, "2", "H") => Self::ShiftHome,
+            (_, "2", "F") => Self::ShiftEnd,
+            (_, "3", "D") => Self::AltLeft,
+            (_, "3", "C") => Self::AltRight,
+            (_, "3", "A") => Self::AltUp,
+            (_, "3", "B") => Self::AltDown,
+            (_, "4", "D") => Self::AltShiftLeft,
+            (_, "4", "C") => Self::AltShiftRight,
+            (_, "4", "A") => Self::AltShiftUp,
+            (_, "4", "B") => Self::AltShiftDown,
+            (_, "5", "D") => Self::CtrlLeft,
+            (_, "5", "C") => Self::CtrlRight,
+            (_, "6", "D") => Self::CtrlShiftLeft,
+            (_, "6", "C") => Self::CtrlShiftRight,
+            (_, "6", "A") => Self::CtrlShiftUp,
+            (_, "6", "B") => Self::CtrlShiftDown,
+            ("3", "5", "~") => Self::CtrlDelete,
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// The movement key a shifted key extends the selection with.
+    pub fn unshifted(self) -> Option<Self> {
+        match self {
+            Self::ShiftLeft | Self::AltShiftLeft => Some(Self::Left),
+            Self::ShiftRight | Self::AltShiftRight => Some(Self::Right),
+            Self::ShiftUp | Self::AltShiftUp => Some(Self::Up),
+            Self::ShiftDown | Self::AltShiftDown => Some(Self::Down),
+            Self::ShiftHome => Some(Self::Home),
+            Self::ShiftEnd => Some(Self::End),
+            Self::CtrlShiftLeft => Some(Self::CtrlLeft),
+            Self::CtrlShiftRight => Some(Self::CtrlRight),
+            _ => None,
+        }
+    }
+
+    /// Whether the key extends a block selection rather than a run of text.
+    pub fn extends_block(self) -> bool {
+        matches!(self, Self::AltShiftLeft | Self::AltShiftRight | Self::AltShiftUp | Self::AltShiftDown)
+    }
+}
+
+/// Names of the keys written as `<Name>`, in both directions.
+const NAMED_KEYS: [(&str, Key); 37] = [
+    ("CR", Key::Char('\n')),
+    ("Tab", Key::Char('\t')),
+    ("lt", Key::Char('<')),
+    ("Esc", Key::Esc),
+    ("BS", Key::Backspace),
+    ("Del", Key::Delete),
+    ("Insert", Key::Insert),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("S-Tab", Key::BackTab),
+    ("S-Left", Key::ShiftLeft),
+    ("S-Right", Key::ShiftRight),
+    ("S-Up", Key::ShiftUp),
+    ("S-Down", Key::ShiftDown),
+    ("S-Home", Key::ShiftHome),
+    ("S-End", Key::ShiftEnd),
+    ("C-Left", Key::CtrlLeft),
+    ("C-Right", Key::CtrlRight),
+    ("C-S-Left", Key::CtrlShiftLeft),
+    ("C-S-Right", Key::CtrlShiftRight),
+    ("C-S-Up", Key::CtrlShiftUp),
+    ("C-S-Down", Key::CtrlShiftDown),
+    ("A-Left", Key::AltLeft),
+    ("A-Right", Key::AltRight),
+    ("A-Up", Key::AltUp),
+    ("A-Down", Key::AltDown),
+    ("A-S-Left", Key::AltShiftLeft),
+    ("A-S-Right", Key::AltShiftRight),
+    ("A-S-Up", Key::AltShiftUp),
+    ("A-S-Down", Key::AltShiftDown),
+    ("C-Del", Key::CtrlDelete),
+];
+
+/// Writes keys in vim-like notation, such as `dw<Esc><C-s>`.
+pub fn format_keys(keys: &[Key]) -> String {
+    let mut res = String::new();
+    for key in keys {
+        if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, named)| named == key) {
+            res.push_str(&format!("<{name}>"));
+            continue;
+        }
+        match key {
+            Key::Char(c) => res.push(*c),
+            Key::Ctrl(c) => res.push_str(&format!("<C-{c}>")),
+            Key::Alt(c) => res.push_str(&format!("<A-{c}>")),
+            Key::F(n) => res.push_str(&format!("<F{n}>")),
+            _ => (),
+        }
+    }
+    res
+}
+
+/// Reads keys written by `format_keys`.
+///
+/// # Errors
+///
+/// Will return a message if a `<...>` key name is not known.
+pub fn parse_keys(notation: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        let closing = rest.find('>').filter(|_| c == '<');
+        let Some(closing) = closing else {
+            keys.push(Key::Char(c));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        let name = &rest[1..closing];
+        rest = &rest[closing + 1..];
+        keys.push(parse_key_name(name).ok_or_else(|| format!("Unknown key: <{name}>"))?);
+    }
+    Ok(keys)
+}
+
+fn parse_key_name(name: &str) -> Option<Key> {
+    if let Some((_, key)) = NAMED_KEYS.iter().find(|(named, _)| *named == name) {
+        return Some(*key);
+    }
+    let single_char = |rest: &str| {
+        let mut chars = rest.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    if let Some(c) = name.strip_prefix("C-").and_then(single_char) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = name.strip_prefix("A-").and_then(single_char) {
+        return Some(Key::Alt(c));
+    }
+    match name.strip_prefix('F')?.parse() {
+        Ok(n @ 1..=12) => Some(Key::F(n)),
+        _ => None,
+    }
+}
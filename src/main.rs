@@ -2,17 +2,43 @@ mod document;
 mod row;
 mod editor;
 mod filetype;
+mod history;
 mod terminal;
 mod highlighting;
+mod config;
+mod command;
+mod mode;
+mod keys;
+mod clipboard;
+mod input_history;
+mod prompt_input;
+mod paths;
+mod glob;
+mod project;
+mod finder;
+mod explorer;
+mod project_search;
+mod pattern;
 
 use editor::Editor;
 pub use terminal::Terminal;
 pub use editor::Position;
 pub use filetype::FileType;
+pub use finder::Finder;
+pub use explorer::Explorer;
+pub use project_search::ProjectSearch;
+pub use history::History;
+pub use input_history::HistoryKind;
+pub use input_history::InputHistory;
+pub use prompt_input::PromptInput;
 pub use filetype::HighlightingOptions;
 pub use row::Row;
 pub use document::Document;
+pub use document::Indent;
+pub use config::Config;
+pub use command::Command;
 pub use editor::SearchDirection;
+pub use keys::Key;
 
 /// This text editor is built using the foundation from this blog:
 /// https://archive.flenker.blog/hecto/
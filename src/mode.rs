@@ -0,0 +1,256 @@
+use crate::Key;
+
+/// The editing mode when `modal` is enabled. Without it the editor always behaves like `Insert`.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,
+    WordBackward,
+    WordEnd,
+    /// `W`, `B` and `E` move by WORDs of non-blank characters.
+    BigWordStart,
+    BigWordBackward,
+    BigWordEnd,
+    MatchingBracket,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+    /// Linewise motions make operators act on whole rows.
+    pub fn is_linewise(self) -> bool {
+        matches!(self, Self::Up | Self::Down | Self::FirstLine | Self::LastLine)
+    }
+
+    /// Inclusive motions make operators include the character the motion ends on.
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Self::WordEnd | Self::BigWordEnd | Self::LineEnd | Self::MatchingBracket)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    /// `gc` toggles comments on the rows it covers.
+    Comment,
+    /// `>` and `<` shift the rows they cover by one level of indentation.
+    Indent,
+    Outdent,
+}
+
+impl Operator {
+    /// Whether the operator always acts on whole rows, whatever motion it is used with.
+    pub fn is_linewise(self) -> bool {
+        matches!(self, Self::Comment | Self::Indent | Self::Outdent)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Motion),
+    /// A doubled operator such as `dd`, acting on `count` whole lines.
+    OperateLines(Operator),
+    /// An operator typed in visual mode, acting on the selection.
+    OperateSelection(Operator),
+    DeleteChar,
+    /// `J` joins `count` rows, at least two.
+    JoinLines,
+    Paste { before: bool },
+    Insert(InsertAt),
+    ToggleVisual,
+    Repeat,
+    Command,
+    Search,
+    /// `n` searches for the last query again, `N` in the opposite direction.
+    SearchNext { reverse: bool },
+    Undo,
+    /// `q` followed by a register starts recording a macro into it.
+    Record(char),
+    /// `@` followed by a register plays its macro, `@@` the one played last.
+    Play(char),
+}
+
+impl Action {
+    /// Whether the action modifies the document and can therefore be repeated with `.`.
+    pub fn is_change(self) -> bool {
+        match self {
+            Self::Operate(operator, _) | Self::OperateLines(operator) => operator != Operator::Yank,
+            Self::DeleteChar | Self::JoinLines | Self::Paste { .. } | Self::Insert(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Collects the keys of a normal or visual mode command until it is complete.
+#[derive(Default)]
+pub struct Pending {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    prefix: Option<char>,
+}
+
+impl Pending {
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && self.operator.is_none() && self.prefix.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Feeds the next key and returns the completed action with its count, if any.
+    ///
+    /// Unknown key sequences are discarded.
+    pub fn feed(&mut self, key: Key, visual: bool) -> Option<(Action, Option<usize>)> {
+        if let Some(prefix) = self.prefix.take() {
+            return match (prefix, key) {
+                ('g', Key::Char('g')) => self.motion(Motion::FirstLine),
+                ('g', Key::Char('c')) => self.operator(Operator::Comment, visual),
+                ('q', Key::Char(c)) if c.is_ascii_alphanumeric() && self.operator.is_none() => {
+                    Some((Action::Record(c), self.count.take()))
+                }
+                ('@', Key::Char(c)) if (c.is_ascii_alphanumeric() || c == '@') && self.operator.is_none() => {
+                    Some((Action::Play(c), self.count.take()))
+                }
+                _ => self.discard(),
+            };
+        }
+        let action = match key {
+            Key::Char(c @ '1'..='9') => return self.push_digit(c),
+            Key::Char('0') if self.count.is_some() => return self.push_digit('0'),
+            Key::Char(c @ ('g' | 'q' | '@')) => {
+                self.prefix = Some(c);
+                return None;
+            }
+            Key::Char('h') | Key::Left | Key::Backspace => return self.motion(Motion::Left),
+            Key::Char('l') | Key::Right | Key::Char(' ') => return self.motion(Motion::Right),
+            Key::Char('k') | Key::Up => return self.motion(Motion::Up),
+            Key::Char('j') | Key::Down => return self.motion(Motion::Down),
+            Key::Char('w') => return self.motion(Motion::WordStart),
+            Key::Char('b') => return self.motion(Motion::WordBackward),
+            Key::Char('e') => return self.motion(Motion::WordEnd),
+            Key::Char('W') => return self.motion(Motion::BigWordStart),
+            Key::Char('B') => return self.motion(Motion::BigWordBackward),
+            Key::Char('E') => return self.motion(Motion::BigWordEnd),
+            Key::Char('%') => return self.motion(Motion::MatchingBracket),
+            Key::Char('0') | Key::Home => return self.motion(Motion::LineStart),
+            Key::Char('$') | Key::End => return self.motion(Motion::LineEnd),
+            Key::Char('G') => return self.motion(Motion::LastLine),
+            // `gcc` comments the current line, like `dd` deletes it.
+            Key::Char('c') if self.operator.is_some_and(|(operator, _)| operator == Operator::Comment) => {
+                return self.operator(Operator::Comment, visual)
+            }
+            Key::Char('d') => return self.operator(Operator::Delete, visual),
+            Key::Char('c') => return self.operator(Operator::Change, visual),
+            Key::Char('y') => return self.operator(Operator::Yank, visual),
+            Key::Char('>') => return self.operator(Operator::Indent, visual),
+            Key::Char('<') => return self.operator(Operator::Outdent, visual),
+            _ if self.operator.is_some() => return self.discard(),
+            Key::Char('x') | Key::Delete if visual => Action::OperateSelection(Operator::Delete),
+            Key::Char('x') | Key::Delete => Action::DeleteChar,
+            Key::Char('D') => Action::Operate(Operator::Delete, Motion::LineEnd),
+            Key::Char('J') => Action::JoinLines,
+            Key::Char('C') => Action::Operate(Operator::Change, Motion::LineEnd),
+            Key::Char('p') => Action::Paste { before: false },
+            Key::Char('P') => Action::Paste { before: true },
+            Key::Char('i') => Action::Insert(InsertAt::Cursor),
+            Key::Char('a') => Action::Insert(InsertAt::After),
+            Key::Char('I') => Action::Insert(InsertAt::LineStart),
+            Key::Char('A') => Action::Insert(InsertAt::LineEnd),
+            Key::Char('o') => Action::Insert(InsertAt::LineBelow),
+            Key::Char('O') => Action::Insert(InsertAt::LineAbove),
+            Key::Char('v') => Action::ToggleVisual,
+            Key::Esc if visual => Action::ToggleVisual,
+            Key::Char('.') => Action::Repeat,
+            Key::Char(':') => Action::Command,
+            Key::Char('/') => Action::Search,
+            Key::Char('n') => Action::SearchNext { reverse: false },
+            Key::Char('N') => Action::SearchNext { reverse: true },
+            Key::Char('u') => Action::Undo,
+            _ => return self.discard(),
+        };
+        Some((action, self.count.take()))
+    }
+
+    fn push_digit(&mut self, digit: char) -> Option<(Action, Option<usize>)> {
+        let digit = digit.to_digit(10).unwrap_or_default() as usize;
+        self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        None
+    }
+
+    fn operator(&mut self, operator: Operator, visual: bool) -> Option<(Action, Option<usize>)> {
+        if visual {
+            let count = self.count.take();
+            return Some((Action::OperateSelection(operator), count));
+        }
+        match self.operator.take() {
+            Some((pending, count)) if pending == operator => {
+                let count = multiply(count, self.count.take());
+                Some((Action::OperateLines(operator), count))
+            }
+            Some(_) => self.discard(),
+            None => {
+                self.operator = Some((operator, self.count.take()));
+                None
+            }
+        }
+    }
+
+    fn motion(&mut self, motion: Motion) -> Option<(Action, Option<usize>)> {
+        let count = self.count.take();
+        match self.operator.take() {
+            Some((operator, operator_count)) => {
+                Some((Action::Operate(operator, motion), multiply(operator_count, count)))
+            }
+            None => Some((Action::Move(motion), count)),
+        }
+    }
+
+    fn discard(&mut self) -> Option<(Action, Option<usize>)> {
+        self.clear();
+        None
+    }
+}
+
+/// Combines the counts typed before an operator and before its motion, as in `2d3w`.
+fn multiply(first: Option<usize>, second: Option<usize>) -> Option<usize> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(first.saturating_mul(second)),
+        (first, second) => first.or(second),
+    }
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Replaces a leading `~` with the home directory.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match (path.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Turns typed input into a path: `~` is expanded and relative paths start at `base`.
+pub fn resolve(input: &str, base: &Path) -> String {
+    let path = expand_tilde(input);
+    if path.is_relative() {
+        base.join(path).to_string_lossy().into_owned()
+    } else {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Shows `path` relative to the working directory if it is inside it.
+pub fn relative_to_cwd(path: &Path) -> String {
+    let cwd = env::current_dir().ok();
+    let relative = cwd.as_deref().and_then(|cwd| path.strip_prefix(cwd).ok()).unwrap_or(path);
+    relative.to_string_lossy().into_owned()
+}
+
+/// Completes the file name at the end of `input` with the entries of its directory, relative
+/// paths starting at `base`. Directories end with `/`, and hidden entries are only offered once
+/// the name starts with a dot.
+pub fn complete(input: &str, base: &Path) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(idx) => input.split_at(idx + 1),
+        None => ("", input),
+    };
+    // Joining an absolute path replaces `base`.
+    let dir_path = base.join(expand_tilde(dir));
+    let Ok(entries) = fs::read_dir(if dir_path.as_os_str().is_empty() { Path::new(".") } else { &dir_path }) else {
+        return Vec::new();
+    };
+    let mut completions: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{name}{slash}"))
+        })
+        .collect();
+    completions.sort();
+    completions
+}
@@ -0,0 +1,14 @@
+use regex::{Regex, RegexBuilder};
+
+/// Compiles a regular expression typed by the user.
+///
+/// # Errors
+///
+/// Will return a message saying what is wrong with `pattern`.
+pub fn compile(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern).case_insensitive(ignore_case).build().map_err(|error| {
+        // The last line of the message says what is wrong, the others point at it.
+        let message = error.to_string();
+        format!("Invalid regex: {}", message.lines().last().unwrap_or_default().trim_start_matches("error: "))
+    })
+}
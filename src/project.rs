@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob;
+
+/// Files and directories that mark the root of a version-controlled project.
+const VCS_MARKERS: [&str; 2] = [".git", ".hg"];
+/// Files that mark the root of a project without version control.
+const MANIFEST_MARKERS: [&str; 5] = ["Cargo.toml", "package.json", "go.mod", "pyproject.toml", "Makefile"];
+/// Listing stops after this many files, so opening the finder in a huge tree stays quick.
+const MAX_FILES: usize = 50_000;
+
+/// The root of the project containing `dir`: the nearest ancestor under version control, or
+/// else the nearest one with a manifest like `Cargo.toml`, or else `dir` itself.
+pub fn root(dir: &Path) -> PathBuf {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let has_marker = |markers: &[&str], ancestor: &Path| markers.iter().any(|marker| ancestor.join(marker).exists());
+    dir.ancestors()
+        .find(|ancestor| has_marker(&VCS_MARKERS, ancestor))
+        .or_else(|| dir.ancestors().find(|ancestor| has_marker(&MANIFEST_MARKERS, ancestor)))
+        .unwrap_or(&dir)
+        .to_path_buf()
+}
+
+/// The files below `root` that are not ignored by a `.gitignore`, as sorted paths relative to it.
+pub fn files(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    walk(root, "", &Gitignore::default(), &mut files);
+    files.sort();
+    files
+}
+
+fn walk(root: &Path, dir: &str, ignore: &Gitignore, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(root.join(dir)) else {
+        return;
+    };
+    let ignore = ignore.with_file(root, dir);
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in entries {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let path = if dir.is_empty() { name.clone() } else { format!("{dir}/{name}") };
+        // Symbolic links to directories are not followed, which could loop.
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        if VCS_MARKERS.contains(&name.as_str()) || ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(root, &path, &ignore, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Rule {
+    pattern: String,
+    /// The directory of the `.gitignore` the rule is from, relative to the root.
+    dir: String,
+    negated: bool,
+    dir_only: bool,
+    /// Whether the pattern contains a `/`, which makes it match paths relative to `dir` rather
+    /// than file names at any depth.
+    anchored: bool,
+}
+
+/// The rules of the `.gitignore` files that apply to a directory, later rules overriding
+/// earlier ones.
+#[derive(Default, Clone)]
+struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    /// Adds the rules of the `.gitignore` in `dir`, if it has one.
+    fn with_file(&self, root: &Path, dir: &str) -> Self {
+        let Ok(contents) = fs::read_to_string(root.join(dir).join(".gitignore")) else {
+            return self.clone();
+        };
+        let mut rules = self.rules.clone();
+        for line in contents.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            rules.push(Rule {
+                pattern: line.trim_start_matches('/').to_string(),
+                dir: dir.to_string(),
+                negated,
+                dir_only,
+                anchored: line.contains('/'),
+            });
+        }
+        Self { rules }
+    }
+
+    fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let relative = if rule.dir.is_empty() {
+                Some(path)
+            } else {
+                path.strip_prefix(&rule.dir).and_then(|rest| rest.strip_prefix('/'))
+            };
+            let Some(relative) = relative else {
+                continue;
+            };
+            let target = if rule.anchored { relative } else { name };
+            if glob::matches(&rule.pattern, target) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::Gitignore;
+
+    /// A fresh directory below the temporary directory, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+            let count = COUNT.fetch_add(1, Ordering::Relaxed);
+            let path = env::temp_dir().join(format!("hecto-test-{}-{nanos}-{count}", process::id()));
+            fs::create_dir(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Reads the `.gitignore` files given as `(dir, contents)` from a temporary directory.
+    fn gitignore(files: &[(&str, &str)]) -> Gitignore {
+        let root = TempDir::new();
+        let mut ignore = Gitignore::default();
+        for (dir, contents) in files {
+            fs::create_dir_all(root.0.join(dir)).unwrap();
+            fs::write(root.0.join(dir).join(".gitignore"), contents).unwrap();
+            ignore = ignore.with_file(&root.0, dir);
+        }
+        ignore
+    }
+
+    #[test]
+    fn negated_rules_override_earlier_ones() {
+        let ignore = gitignore(&[("", "*.log\n!keep.log\n")]);
+        assert!(ignore.is_ignored("debug.log", false));
+        assert!(ignore.is_ignored("logs/debug.log", false));
+        assert!(!ignore.is_ignored("keep.log", false));
+        assert!(!ignore.is_ignored("logs/keep.log", false));
+    }
+
+    #[test]
+    fn anchored_rules_match_relative_paths() {
+        let ignore = gitignore(&[("", "/build\ndoc/out\n")]);
+        assert!(ignore.is_ignored("build", true));
+        assert!(!ignore.is_ignored("src/build", true));
+        assert!(ignore.is_ignored("doc/out", true));
+        assert!(!ignore.is_ignored("src/doc/out", true));
+    }
+
+    #[test]
+    fn rules_apply_below_their_directory() {
+        let ignore = gitignore(&[("", ""), ("sub", "/gen\n")]);
+        assert!(ignore.is_ignored("sub/gen", true));
+        assert!(!ignore.is_ignored("gen", true));
+        assert!(!ignore.is_ignored("other/gen", true));
+    }
+
+    #[test]
+    fn directory_rules_skip_files() {
+        let ignore = gitignore(&[("", "tmp/\n")]);
+        assert!(ignore.is_ignored("tmp", true));
+        assert!(!ignore.is_ignored("tmp", false));
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::glob;
+use crate::pattern;
+use crate::project;
+use crate::Position;
+
+/// A search stops after this many matches.
+const MAX_MATCHES: usize = 10_000;
+/// Matching lines are shown up to this many characters.
+const MAX_TEXT_LEN: usize = 200;
+
+/// A line of a project file that matches the search.
+pub struct Match {
+    /// The file, relative to the root of the project.
+    pub path: String,
+    pub position: Position,
+    text: String,
+}
+
+/// A search through the files of a project, which runs in a thread of its own and sends the
+/// matching lines as it finds them.
+pub struct ProjectSearch {
+    pub root: PathBuf,
+    pub query: String,
+    matches: Vec<Match>,
+    /// Receives the matches until the search is done.
+    receiver: Option<Receiver<Match>>,
+    /// Tells the thread to stop, once the search is closed.
+    cancelled: Arc<AtomicBool>,
+}
+
+/// A project search as typed: a regular expression, optionally followed by `-g <glob>` filters.
+/// Only files matching one of the globs are searched, and none matching a glob that starts
+/// with `!`.
+pub struct Query {
+    pub input: String,
+    pub pattern: String,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+}
+
+impl Query {
+    /// Splits the `-g <glob>` filters off the end of `input`, so that a regular expression may
+    /// still contain ` -g ` itself. One that ends in `-g <word>` can be written with `\-g`.
+    pub fn parse(input: &str) -> Self {
+        let mut pattern = input;
+        let mut globs = Vec::new();
+        while let Some((rest, glob)) = pattern.trim_end().rsplit_once(' ') {
+            let Some(rest) = rest.trim_end().strip_suffix("-g").filter(|rest| rest.ends_with(' ')) else {
+                break;
+            };
+            globs.push(glob.to_string());
+            pattern = rest.trim_end();
+        }
+        globs.reverse();
+        let (excludes, includes): (Vec<String>, Vec<String>) = globs.into_iter().partition(|glob| glob.starts_with('!'));
+        Self {
+            input: input.to_string(),
+            pattern: pattern.to_string(),
+            includes,
+            excludes: excludes.iter().map(|glob| glob[1..].to_string()).collect(),
+        }
+    }
+}
+
+impl ProjectSearch {
+    /// Starts searching the files of the project `root` for `query`.
+    ///
+    /// # Errors
+    ///
+    /// Will return a message if the regular expression is invalid.
+    pub fn start(root: PathBuf, query: Query, ignore_case: bool) -> Result<Self, String> {
+        let regex = pattern::compile(&query.pattern, ignore_case)?;
+        let Query { input, includes, excludes, .. } = query;
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_root = root.clone();
+        let thread_cancelled = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            let mut found = 0;
+            for path in project::files(&thread_root) {
+                if thread_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let included = includes.is_empty() || includes.iter().any(|glob| matches_glob(glob, &path));
+                if !included || excludes.iter().any(|glob| matches_glob(glob, &path)) {
+                    continue;
+                }
+                // Files that are not UTF-8, like most binary files, are skipped.
+                let Ok(contents) = fs::read_to_string(thread_root.join(&path)) else {
+                    continue;
+                };
+                for found_match in search_file(&regex, &path, &contents) {
+                    found += 1;
+                    if sender.send(found_match).is_err() || found >= MAX_MATCHES {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            root,
+            query: input,
+            matches: Vec::new(),
+            receiver: Some(receiver),
+            cancelled,
+        })
+    }
+
+    /// Takes in the matches found since the last call, returning whether there were any or the
+    /// search is done.
+    pub fn update(&mut self) -> bool {
+        let Some(receiver) = &self.receiver else {
+            return false;
+        };
+        let old_len = self.matches.len();
+        loop {
+            match receiver.try_recv() {
+                Ok(found_match) => self.matches.push(found_match),
+                Err(TryRecvError::Empty) => return self.matches.len() > old_len,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return true;
+                }
+            }
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// The match listed in `row` of the results.
+    pub fn match_at(&self, row: usize) -> Option<&Match> {
+        row.checked_sub(1).and_then(|idx| self.matches.get(idx))
+    }
+
+    /// The results: a summary, then one `path:line: text` row per match.
+    pub fn text(&self) -> String {
+        let files = self.matches.windows(2).filter(|pair| pair[0].path != pair[1].path).count() + 1;
+        let state = if self.is_running() {
+            " (searching...)"
+        } else if self.matches.len() >= MAX_MATCHES {
+            " (stopped)"
+        } else {
+            ""
+        };
+        let mut text = match self.matches.len() {
+            0 => format!("No matches for {} in {}{state}\n", self.query, self.root.display()),
+            len => format!(
+                "{len} {} in {files} {} for {} in {}{state}\n",
+                if len == 1 { "match" } else { "matches" },
+                if files == 1 { "file" } else { "files" },
+                self.query,
+                self.root.display()
+            ),
+        };
+        for found_match in &self.matches {
+            text.push_str(&format!("{}:{}: {}\n", found_match.path, found_match.position.y + 1, found_match.text));
+        }
+        text
+    }
+}
+
+impl Drop for ProjectSearch {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The lines of a file that match `regex`, at their first match.
+fn search_file(regex: &Regex, path: &str, contents: &str) -> Vec<Match> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(y, line)| {
+            let found = regex.find(line)?;
+            let x = line[..found.start()].graphemes(true).count();
+            Some(Match {
+                path: path.to_string(),
+                position: Position { x, y },
+                text: line.trim().chars().take(MAX_TEXT_LEN).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Whether the project file `path` matches a filter glob. Globs with a `/` match the whole path,
+/// others any of its file and directory names.
+fn matches_glob(glob: &str, path: &str) -> bool {
+    if glob.contains('/') {
+        glob::matches(glob.trim_start_matches('/'), path)
+    } else {
+        Path::new(path).iter().any(|name| glob::matches(glob, &name.to_string_lossy()))
+    }
+}
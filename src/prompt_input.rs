@@ -0,0 +1,104 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Key;
+use crate::Row;
+
+/// The text typed into a prompt, with a cursor between its graphemes.
+#[derive(Default)]
+pub struct PromptInput {
+    text: String,
+    cursor: usize,
+    /// The display column the visible part of the text starts at, once it is too long to show.
+    scroll: usize,
+}
+
+impl PromptInput {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, leaving the cursor at its end.
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.len();
+    }
+
+    /// Inserts `text` at the cursor, up to its first newline and without control characters.
+    pub fn insert(&mut self, text: &str) {
+        let text: String = text.chars().take_while(|c| *c != '\n').filter(|c| !c.is_control()).collect();
+        let at = self.byte_index(self.cursor);
+        self.text.insert_str(at, &text);
+        self.cursor = self.text[..at + text.len()].graphemes(true).count();
+    }
+
+    /// Moves the cursor or edits the text for line editing keys, returning whether `key` is one.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        let len = self.len();
+        match key {
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(len),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = len,
+            Key::CtrlLeft | Key::AltLeft => self.cursor = self.prev_word_start(),
+            Key::CtrlRight | Key::AltRight => self.cursor = self.next_word_end(),
+            Key::Backspace if self.cursor > 0 => self.delete(self.cursor - 1, self.cursor),
+            Key::Delete => self.delete(self.cursor, (self.cursor + 1).min(len)),
+            Key::Ctrl('w') => self.delete(self.prev_word_start(), self.cursor),
+            Key::CtrlDelete | Key::Alt('d') => self.delete(self.cursor, self.next_word_end()),
+            Key::Ctrl('u') => self.delete(0, self.cursor),
+            Key::Ctrl('k') => self.delete(self.cursor, len),
+            Key::Char(c) if !c.is_control() => self.insert(&c.to_string()),
+            Key::Backspace => (),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The part of the text that fits into `width` columns, scrolled so that the cursor is
+    /// visible, and the display column of the cursor within it.
+    pub fn view(&mut self, width: usize) -> (&str, usize) {
+        let row = Row::from(self.text.as_str());
+        let column = |idx: usize| row.column(idx, 1);
+        let cursor = column(self.cursor);
+        let width = width.max(1);
+        if cursor < self.scroll {
+            self.scroll = cursor;
+        } else if cursor >= self.scroll + width {
+            self.scroll = cursor + 1 - width;
+        }
+        // Wide characters cut off by either edge are left out.
+        let mut start = row.index(self.scroll, 1);
+        if column(start) < self.scroll {
+            start += 1;
+        }
+        let end = row.index(self.scroll + width, 1);
+        let start_column = column(start);
+        let start_byte = self.byte_index(start);
+        let end_byte = self.byte_index(end.max(start));
+        (&self.text[start_byte..end_byte], cursor - start_column)
+    }
+
+    fn len(&self) -> usize {
+        self.text.graphemes(true).count()
+    }
+
+    fn byte_index(&self, idx: usize) -> usize {
+        self.text.grapheme_indices(true).nth(idx).map_or(self.text.len(), |(byte_idx, _)| byte_idx)
+    }
+
+    fn delete(&mut self, start: usize, end: usize) {
+        let (start_byte, end_byte) = (self.byte_index(start), self.byte_index(end));
+        self.text.replace_range(start_byte..end_byte, "");
+        self.cursor = start;
+    }
+
+    fn prev_word_start(&self) -> usize {
+        let words = Row::from(self.text.as_str()).words(false);
+        words.into_iter().rev().find(|word| word.start < self.cursor).map_or(0, |word| word.start)
+    }
+
+    fn next_word_end(&self) -> usize {
+        let words = Row::from(self.text.as_str()).words(false);
+        words.into_iter().find(|word| word.end > self.cursor).map_or(self.len(), |word| word.end)
+    }
+}
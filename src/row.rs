@@ -1,14 +1,17 @@
 use std::cmp;
+use std::ops::Range;
 use std::path::is_separator;
 
+use regex::Regex;
 use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::highlighting;
+use crate::highlighting::{self, Overlay};
+use crate::FileType;
 use crate::HighlightingOptions;
 use crate::SearchDirection;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Row {
     string: String,
     highlighting: Vec<highlighting::Type>,
@@ -26,33 +29,85 @@ impl From<&str> for Row {
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    /// Renders the part of the row between the display columns `start` and `end`, drawing the
+    /// graphemes within the ranges of `overlays` with the first overlay that contains them.
+    pub fn render(&self, start: usize, end: usize, tab_width: usize, overlays: &[(Range<usize>, Overlay)]) -> String {
         let mut res = String::new();
         let mut current_highilghting = &highlighting::Type::None;
+        let mut current_overlay = None;
+        let mut column = 0;
 
-        for (idx, grapheme) in self.string[..].graphemes(true).enumerate().skip(start).take(end - start) {
-            if let Some(c) = grapheme.chars().next() {
-                let highlighting_type = self.highlighting.get(idx).unwrap_or(&highlighting::Type::None);
-                if highlighting_type != current_highilghting {
-                    current_highilghting = highlighting_type;
-                    let start_hightlight = format!("{}", termion::color::Fg(highlighting_type.to_color()));
-                    res.push_str(&start_hightlight[..]);
+        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
+            let width = grapheme_width(grapheme, column, tab_width);
+            if column.saturating_add(width) <= start {
+                column = column.saturating_add(width);
+                continue;
+            }
+            let highlighting_type = self.highlighting.get(idx).unwrap_or(&highlighting::Type::None);
+            if highlighting_type != current_highilghting {
+                current_highilghting = highlighting_type;
+                let start_hightlight = format!("{}", termion::color::Fg(highlighting_type.to_color()));
+                res.push_str(&start_hightlight[..]);
+            }
+            let overlay = overlays
+                .iter()
+                .find(|(range, _)| range.contains(&idx))
+                .map(|(_, overlay)| *overlay);
+            if overlay != current_overlay {
+                if let Some(previous) = current_overlay {
+                    res.push_str(&previous.end());
                 }
-                if c == '\t' {
-                    res.push_str(" ");
-                } else {
-                    res.push(c)
+                if let Some(overlay) = overlay {
+                    res.push_str(&overlay.start());
                 }
+                current_overlay = overlay;
+            }
+            // Tabs, and wide characters cut off at either edge, are drawn as spaces.
+            if grapheme == "\t" || column < start || column.saturating_add(width) > end {
+                let visible = cmp::min(column.saturating_add(width), end) - cmp::max(column, start);
+                res.push_str(&" ".repeat(visible));
+            } else {
+                res.push_str(grapheme);
             }
+            column = column.saturating_add(width);
         }
 
+        if let Some(overlay) = current_overlay {
+            res.push_str(&overlay.end());
+        }
+        // An overlay past the last grapheme, like a cursor at the end of the row, shows as a space.
+        let row_end = overlays.iter().find(|(range, _)| range.contains(&self.len));
+        if let Some((_, overlay)) = row_end.filter(|_| column >= start && column < end) {
+            res.push_str(&format!("{} {}", overlay.start(), overlay.end()));
+        }
         let end_highlight = format!("{}", color::Fg(color::Reset));
         res.push_str(&end_highlight[..]);
         res
     }
 
+    /// Returns the display column at which the grapheme at `pos_in_line` starts.
+    pub fn column(&self, pos_in_line: usize, tab_width: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(pos_in_line)
+            .fold(0, |column, grapheme| column + grapheme_width(grapheme, column, tab_width))
+    }
+
+    /// Returns the grapheme at display column `column`, or the row length if the row is shorter.
+    pub fn index(&self, column: usize, tab_width: usize) -> usize {
+        let mut current = 0;
+        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
+            current += grapheme_width(grapheme, current, tab_width);
+            if current > column {
+                return idx;
+            }
+        }
+        self.len
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -103,81 +158,143 @@ impl Row {
     }
 
     pub fn split(&mut self, pos_in_line: usize) -> Self {
-        let mut row: String = String::new();
-        let mut length = 0;
-        let mut splitted_row: String = String::new();
-        let mut splitted_length = 0;
+        let splitted_row = self.string.split_off(self.byte_index(pos_in_line));
+        self.len = cmp::min(pos_in_line, self.len);
+        Self::from(&splitted_row[..])
+    }
 
-        for (idx, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if idx < pos_in_line {
-                length += 1;
-                row.push_str(grapheme);
+    /// Returns the grapheme at `pos_in_line`, if the row is that long.
+    pub fn grapheme(&self, pos_in_line: usize) -> Option<&str> {
+        self.string[..].graphemes(true).nth(pos_in_line)
+    }
+
+    /// Returns the text between the graphemes `start` and `end`.
+    pub fn slice(&self, start: usize, end: usize) -> &str {
+        let end = self.byte_index(end);
+        let start = cmp::min(self.byte_index(start), end);
+        &self.string[start..end]
+    }
+
+    /// The brackets in the row with their positions, leaving out those in strings, characters
+    /// and comments.
+    pub fn brackets(&self) -> Vec<(usize, char)> {
+        self.string[..]
+            .graphemes(true)
+            .enumerate()
+            .filter_map(|(idx, grapheme)| {
+                let c = grapheme.chars().next()?;
+                let in_literal = matches!(
+                    self.highlighting.get(idx),
+                    Some(highlighting::Type::String | highlighting::Type::Character | highlighting::Type::Comment)
+                );
+                (FileType::bracket_pair(c).is_some() && !in_literal).then_some((idx, c))
+            })
+            .collect()
+    }
+
+    /// The grapheme ranges of the words in the row, split at Unicode word boundaries, or of the
+    /// runs of non-blank characters with `big`.
+    pub fn words(&self, big: bool) -> Vec<Range<usize>> {
+        let mut words: Vec<Range<usize>> = Vec::new();
+        let mut idx = 0;
+        for segment in self.string.split_word_bounds() {
+            let len = segment.graphemes(true).count();
+            let is_word = if big {
+                !segment.trim().is_empty()
             } else {
-                splitted_length = length + 1;
-                splitted_row.push_str(grapheme);
+                segment.chars().any(char::is_alphanumeric)
+            };
+            if is_word {
+                match words.last_mut() {
+                    // Non-blank segments next to each other form a single WORD.
+                    Some(last) if big && last.end == idx => last.end = idx + len,
+                    _ => words.push(idx..idx + len),
+                }
             }
+            idx += len;
         }
+        words
+    }
 
-        self.string = row;
-        self.len = length;
-        Self {
-            string: splitted_row,
-            highlighting: Vec::new(),
-            len: splitted_length,
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    fn byte_index(&self, pos_in_line: usize) -> usize {
+        self.string[..]
+            .grapheme_indices(true)
+            .nth(pos_in_line)
+            .map_or(self.string.len(), |(idx, _)| idx)
+    }
+
+    /// Replaces the first (or with `global` every) match of `pattern` and returns how many
+    /// matches were replaced. The replacement can refer to groups of the match, like `$1`.
+    pub fn replace(&mut self, pattern: &Regex, replacement: &str, global: bool) -> usize {
+        let count = if global {
+            pattern.find_iter(&self.string).count()
+        } else {
+            usize::from(pattern.is_match(&self.string))
+        };
+        if count > 0 {
+            self.string = if global {
+                pattern.replace_all(&self.string, replacement).into_owned()
+            } else {
+                pattern.replace(&self.string, replacement).into_owned()
+            };
+            self.len = self.string[..].graphemes(true).count();
         }
+        count
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
 
-    pub fn find(&self, query: &str, pos: usize, dir: SearchDirection) -> Option<usize> {
-        if pos > self.len || query.is_empty() {
+    /// Returns the first match of `query` at or after `pos`, or going backward, the last one
+    /// that ends before `pos`. Matches overlapping an earlier one are skipped, as in `find_all`.
+    pub fn find(&self, query: &str, pos: usize, dir: SearchDirection, ignore_case: bool) -> Option<usize> {
+        if pos > self.len {
             return None;
         }
-        let start = if dir == SearchDirection::Forward {
-            pos
-        } else {
-            0
-        };
-        let end = if dir == SearchDirection::Forward {
-            self.len
-        } else {
-            pos
-        };
-        let substring: String = self.string[..].graphemes(true).skip(start).take(end - start).collect();
-        let matching_byte_idx = if dir == SearchDirection::Forward {
-            substring.find(query)
-        } else {
-            substring.rfind(query)
-        };
-        if let Some(matching_byte_idx) = matching_byte_idx {
-            for (grapheme_idx, (byte_idx, _)) in substring[..].grapheme_indices(true).enumerate() {
-                if matching_byte_idx == byte_idx {
-                    return Some(start + grapheme_idx);
-                }
-            }
+        let len = query.graphemes(true).count();
+        let starts = self.find_all(query, ignore_case);
+        match dir {
+            SearchDirection::Forward => starts.into_iter().find(|start| *start >= pos),
+            SearchDirection::Backward => starts.into_iter().rev().find(|start| start + len <= pos),
         }
-        None
     }
 
-    fn highlight_search_res(&mut self, word: Option<&str>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
+    /// The grapheme indices where `query` starts in the row, without overlapping matches.
+    pub fn find_all(&self, query: &str, ignore_case: bool) -> Vec<usize> {
+        let len = query.graphemes(true).count();
+        let mut res: Vec<usize> = Vec::new();
+        for start in self.match_starts(query, ignore_case) {
+            if res.last().is_none_or(|last| start >= last + len) {
+                res.push(start);
             }
-            let mut idx = 0;
-            while let Some(search_match) = self.find(word, idx, SearchDirection::Forward) {
-                if let Some(next_idx) = search_match.checked_add(word[..].graphemes(true).count()) {
-                    for i in search_match..next_idx {
-                        self.highlighting[i] = highlighting::Type::SearchResult;
-                    }
-                    idx = next_idx;
-                } else {
-                    break;
-                }
+        }
+        res
+    }
+
+    /// The grapheme indices where `query` starts, overlapping matches included. Graphemes are
+    /// compared whole, and with `ignore_case` by their case folded forms, so that `ς`, `σ` and
+    /// `Σ` all match each other.
+    fn match_starts(&self, query: &str, ignore_case: bool) -> Vec<usize> {
+        let fold = |grapheme: &str| {
+            if ignore_case {
+                grapheme.to_uppercase().to_lowercase()
+            } else {
+                grapheme.to_string()
             }
+        };
+        let query: Vec<String> = query.graphemes(true).map(fold).collect();
+        let row: Vec<String> = self.string.graphemes(true).map(fold).collect();
+        if query.is_empty() || row.len() < query.len() {
+            return Vec::new();
         }
+        (0..=row.len() - query.len())
+            .filter(|start| row[*start..*start + query.len()] == query[..])
+            .collect()
     }
 
     fn highlight_str(&mut self, index: &mut usize, substring: &str, chars: &[char], hl_type: highlighting::Type) -> bool {
@@ -216,7 +333,7 @@ impl Row {
                 }
             }
 
-            if self.highlight_str(idx, &word, chars, hl_type) {
+            if self.highlight_str(idx, word, chars, hl_type) {
                 return true;
             }
         }
@@ -259,6 +376,7 @@ impl Row {
                     for _ in *idx..chars.len() {
                         self.highlighting.push(highlighting::Type::Comment);
                     }
+                    *idx = chars.len();
                     return true;
                 }
             };
@@ -310,15 +428,15 @@ impl Row {
         false
     }
 
-    pub fn highlight(&mut self, opts: &HighlightingOptions, word: Option<&str>) {
+    pub fn highlight(&mut self, opts: &HighlightingOptions) {
         self.highlighting = Vec::new();
         let chars: Vec<char> = self.string.chars().collect();
         let mut idx = 0;
         while let Some(c) = chars.get(idx) {
             if self.highlight_char(&mut idx, opts, *c, &chars)
                 || self.highilght_comment(&mut idx, opts, *c, &chars)
-                || self.highlight_primary_keywords(&mut idx, &opts, &chars)
-                || self.highlight_secondary_keywords(&mut idx, &opts, &chars)
+                || self.highlight_primary_keywords(&mut idx, opts, &chars)
+                || self.highlight_secondary_keywords(&mut idx, opts, &chars)
                 || self.highlight_string(&mut idx, opts, *c, &chars)
                 || self.highlight_number(&mut idx, opts, *c, &chars) {
                 continue;
@@ -326,10 +444,55 @@ impl Row {
             self.highlighting.push(highlighting::Type::None);
             idx += 1;
         }
-        self.highlight_search_res(word);
     }
+}
+
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_width - column % tab_width
+    } else if grapheme.chars().next().is_some_and(is_wide) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Ranges of characters that take up two columns in a terminal: the East Asian wide and
+/// fullwidth blocks, and emoji.
+const WIDE_CHARS: [(char, char); 16] = [
+    ('\u{1100}', '\u{115F}'),
+    ('\u{2E80}', '\u{303E}'),
+    ('\u{3041}', '\u{33FF}'),
+    ('\u{3400}', '\u{4DBF}'),
+    ('\u{4E00}', '\u{9FFF}'),
+    ('\u{A000}', '\u{A4CF}'),
+    ('\u{AC00}', '\u{D7A3}'),
+    ('\u{F900}', '\u{FAFF}'),
+    ('\u{FE30}', '\u{FE4F}'),
+    ('\u{FF00}', '\u{FF60}'),
+    ('\u{FFE0}', '\u{FFE6}'),
+    ('\u{1F300}', '\u{1F64F}'),
+    ('\u{1F680}', '\u{1F6FF}'),
+    ('\u{1F900}', '\u{1F9FF}'),
+    ('\u{20000}', '\u{2FFFD}'),
+    ('\u{30000}', '\u{3FFFD}'),
+];
+
+fn is_wide(c: char) -> bool {
+    WIDE_CHARS.iter().any(|(first, last)| (*first..=*last).contains(&c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Row;
+    use crate::SearchDirection;
 
-    fn is_seperator(c: char) -> bool {
-        c.is_ascii_punctuation() || c.is_ascii_whitespace()
+    #[test]
+    fn find_skips_overlapping_matches() {
+        let row = Row::from("aaaa");
+        assert_eq!(row.find_all("aa", false), vec![0, 2]);
+        assert_eq!(row.find("aa", 1, SearchDirection::Forward, false), Some(2));
+        assert_eq!(row.find("aa", 3, SearchDirection::Backward, false), Some(0));
+        assert_eq!(row.find("aa", 4, SearchDirection::Backward, false), Some(2));
     }
 }
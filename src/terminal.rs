@@ -1,37 +1,78 @@
-use std::io::{self, stdout, Write, Stdout, Error};
+use std::io::{self, stdin, stdout, Write, Stdout, Error, ErrorKind};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use termion::color::{Rgb, Bg, Fg, Reset};
-use termion::event::Key;
-use termion::input::TermRead;
+use termion::event::{self, Event};
+use termion::input::TermReadEventsAndRaw;
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::clear::{CurrentLine, All};
-use termion::cursor::{Show, Goto, Hide};
+use termion::cursor::{Show, Goto, Hide, SteadyBar, SteadyBlock};
 
+use crate::Key;
 use crate::Position;
 
 pub struct Size {            
     pub width: u16,  
     pub height: u16,          
 }            
+/// Start and end of text pasted while bracketed paste is enabled.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// What the user typed: a key, or text pasted all at once.
+pub enum Input {
+    Key(Key),
+    Paste(String),
+}
+
 pub struct Terminal {   
     size: Size,     
-    _stdout: RawTerminal<Stdout>    
+    _stdout: RawTerminal<Stdout>,
+    /// Events read from stdin by a thread of their own, so that reading can time out, with the
+    /// bytes each was read from.
+    events: Receiver<Result<(Event, Vec<u8>), Error>>,
 }            
                                
 impl Terminal {     
     /// # Errors
     /// 
     /// Will return an `std::io::Error` 
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self, Error> {            
         let size = termion::terminal_size()?;            
+        Self::enable_bracketed_paste();
         Ok(Self {            
             size: Size {            
                 width: size.0,            
                 height: size.1.saturating_sub(2),            
             },    
             _stdout: stdout().into_raw_mode()?,        
+            events: Self::spawn_reader(),
         })            
     }          
 
+    fn spawn_reader() -> Receiver<Result<(Event, Vec<u8>), Error>> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for event in stdin().events_and_raw() {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Makes the terminal mark pasted text, so that it is read as one `Input::Paste`.
+    fn enable_bracketed_paste() {
+        print!("\x1b[?2004h");
+    }
+
+    pub fn disable_bracketed_paste() {
+        print!("\x1b[?2004l");
+    }
+
     #[must_use]
     pub fn size(&self) -> &Size {            
         &self.size            
@@ -50,8 +91,8 @@ impl Terminal {
         let x = x.saturating_add(1);
         let y = y.saturating_add(1);
         print!("{}", Goto(
-            x.try_into().expect(format!("Wasn't able to move the cursor to x: {x}").as_str()), 
-            y.try_into().expect(format!("Wasn't able to move the cursor to y: {y}").as_str())
+            x.try_into().unwrap_or_else(|_| panic!("Wasn't able to move the cursor to x: {x}")),
+            y.try_into().unwrap_or_else(|_| panic!("Wasn't able to move the cursor to y: {y}"))
         ));
     }
 
@@ -65,13 +106,56 @@ impl Terminal {
     /// # Errors
     /// 
     /// Will return an `std::io::Error` if there was a problem reading a Key.
-    pub fn read_key() -> Result<Key, Error> {
+    pub fn read_input(&mut self) -> Result<Input, Error> {
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            let (event, _) = self.next_event()?;
+            if let Some(input) = self.input_from(event)? {
+                return Ok(input);
             }
         }
-    }   
+    }
+
+    /// Like `read_input`, but gives up after `timeout` without any input.
+    ///
+    /// # Errors
+    ///
+    /// Will return an `std::io::Error` if there was a problem reading a Key.
+    pub fn poll_input(&mut self, timeout: Duration) -> Result<Option<Input>, Error> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => self.input_from(event?.0),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::new(ErrorKind::UnexpectedEof, "Input closed")),
+        }
+    }
+
+    fn next_event(&self) -> Result<(Event, Vec<u8>), Error> {
+        self.events.recv().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "Input closed"))?
+    }
+
+    /// The input an event stands for, if any.
+    fn input_from(&mut self, event: Event) -> Result<Option<Input>, Error> {
+        Ok(match event {
+            Event::Key(key) => Some(Input::Key(Key::from(key))),
+            Event::Unsupported(bytes) if bytes == PASTE_START => Some(Input::Paste(self.read_paste()?)),
+            Event::Unsupported(bytes) => Key::from_escape_sequence(&bytes).map(Input::Key),
+            Event::Mouse(_) => None,
+        })
+    }
+
+    /// Reads pasted text up to the end of the paste. Only characters are kept, so control keys
+    /// within it cannot trigger any command.
+    fn read_paste(&mut self) -> Result<String, Error> {
+        let mut text = String::new();
+        loop {
+            match self.next_event()? {
+                // Both `\r` and `\n` are read as a newline, so `\r\n` line breaks would double.
+                (Event::Key(event::Key::Char(_)), raw) if raw == b"\r" => text.push('\r'),
+                (Event::Key(event::Key::Char(c)), _) => text.push(c),
+                (Event::Unsupported(bytes), _) if bytes == PASTE_END => return Ok(normalize_line_breaks(&text)),
+                _ => (),
+            }
+        }
+    }
 
     pub fn cursor_hide() {
         print!("{Hide}");
@@ -81,6 +165,24 @@ impl Terminal {
         print!("{Show}");
     }   
 
+    pub fn cursor_block() {
+        print!("{SteadyBlock}");
+    }
+
+    pub fn cursor_bar() {
+        print!("{SteadyBar}");
+    }
+
+    /// Restores the cursor shape configured in the terminal; termion has no sequence for it.
+    pub fn cursor_default_shape() {
+        print!("\x1b[0 q");
+    }
+
+    /// Sets the system clipboard with the OSC 52 escape sequence, for terminals that support it.
+    pub fn set_clipboard(text: &str) {
+        print!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    }
+
     pub fn clear_current_line() {
         print!("{CurrentLine}");
     }   
@@ -100,4 +202,37 @@ impl Terminal {
     pub fn reset_fg_color() {
         print!("{}", Fg(Reset));
     }
-}
\ No newline at end of file
+}
+
+/// Turns `\r\n` and lone `\r` line breaks into `\n`.
+fn normalize_line_breaks(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let byte = |idx: usize| u32::from(chunk.get(idx).copied().unwrap_or(0));
+        let group = byte(0) << 16 | byte(1) << 8 | byte(2);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                res.push(char::from(ALPHABET[(group >> (18 - 6 * idx)) as usize & 63]));
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_line_breaks;
+
+    #[test]
+    fn pasted_crlf_line_breaks_become_newlines() {
+        assert_eq!(normalize_line_breaks("one\r\ntwo\r\n"), "one\ntwo\n");
+        assert_eq!(normalize_line_breaks("one\rtwo\n\nthree"), "one\ntwo\n\nthree");
+    }
+}